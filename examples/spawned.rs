@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use redis_subscribe::RedisSub;
+use tokio::time::sleep;
+use tokio_stream::StreamExt;
+
+#[tokio::main]
+pub async fn main() {
+    // Connect to the Redis server in a background task.
+    let (handle, mut receiver) = RedisSub::new("localhost:6379")
+        .spawn()
+        .await
+        .expect("failed to connect to Redis");
+
+    // The receiver is 'static, so it can be moved to another task.
+    tokio::spawn(async move {
+        while let Some(msg) = receiver.next().await {
+            println!("got = {:?}", msg);
+        }
+    });
+
+    // The handle can be cloned to manage subscriptions from anywhere.
+    let channel2_handle = handle.clone();
+    handle.subscribe("channel1".to_string()).await.unwrap();
+    tokio::spawn(async move {
+        channel2_handle
+            .subscribe("channel2".to_string())
+            .await
+            .unwrap()
+    });
+
+    // Sleep for 10 seconds, dropping the handle afterwards stops the connection.
+    sleep(Duration::from_millis(10 * 1000)).await;
+}
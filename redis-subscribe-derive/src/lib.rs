@@ -0,0 +1,199 @@
+//! Derive macros for `redis-subscribe`, enabled by its `derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr, Path};
+
+/// Implement `Topic` for a struct, from a channel name template and a codec.
+///
+/// ```ignore
+/// #[derive(Topic, Serialize, Deserialize)]
+/// #[topic(channel = "orders.{region}.{id}", codec = Json)]
+/// struct OrderCreated {
+///     region: String,
+///     id: u64,
+///     amount: u32,
+/// }
+/// ```
+///
+/// Every parameter of the template must be a distinct field of the struct,
+/// of a type implementing `Display`, `FromStr`, `Clone`, `Debug` and `PartialEq`.
+/// The parameters are declared as a struct named after the struct with a `Params` suffix.
+#[proc_macro_derive(Topic, attributes(topic))]
+pub fn derive_topic(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// The names of the parameters of a template, in the order they appear in.
+fn params(template: &LitStr) -> syn::Result<Vec<String>> {
+    let value = template.value();
+    let mut params = Vec::new();
+    let mut rest = value.as_str();
+    let mut follows_param = false;
+
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(syn::Error::new(template.span(), "unclosed parameter")),
+        };
+
+        // The value of a parameter ends where the next literal text starts.
+        if start == 0 && follows_param {
+            return Err(syn::Error::new(
+                template.span(),
+                "parameters must be separated by text",
+            ));
+        }
+        let name = &rest[start + 1..end];
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(syn::Error::new(
+                template.span(),
+                format!("invalid parameter name `{}`", name),
+            ));
+        }
+        if params.iter().any(|param| param == name) {
+            return Err(syn::Error::new(
+                template.span(),
+                format!("duplicate parameter `{}`", name),
+            ));
+        }
+
+        params.push(name.to_string());
+        rest = &rest[end + 1..];
+        follows_param = true;
+    }
+
+    Ok(params)
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let mut channel: Option<LitStr> = None;
+    let mut codec: Option<Path> = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("topic"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("channel") {
+                channel = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("codec") {
+                codec = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `channel` or `codec`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let ident = &input.ident;
+    let channel = channel.ok_or_else(|| {
+        syn::Error::new_spanned(ident, "missing `#[topic(channel = \"...\")]` attribute")
+    })?;
+    let codec = codec.ok_or_else(|| {
+        syn::Error::new_spanned(ident, "missing `#[topic(codec = ...)]` attribute")
+    })?;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "topics cannot be generic",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "topics must have named fields",
+                ))
+            }
+        },
+        _ => return Err(syn::Error::new_spanned(ident, "topics must be structs")),
+    };
+
+    // Find the field of every parameter.
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    for param in params(&channel)? {
+        let field = fields
+            .iter()
+            .find(|field| field.ident.as_ref().is_some_and(|name| *name == param))
+            .ok_or_else(|| {
+                syn::Error::new(
+                    channel.span(),
+                    format!("parameter `{}` is not a field of `{}`", param, ident),
+                )
+            })?;
+        names.push(field.ident.clone());
+        types.push(field.ty.clone());
+    }
+
+    let vis = &input.vis;
+    let params = format_ident!("{}Params", ident);
+    let indices = 0..names.len();
+    let doc = format!("Parameters of the channel of [{}].", ident);
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, PartialEq)]
+        #vis struct #params {
+            #( pub #names: #types, )*
+        }
+
+        impl ::redis_subscribe::Topic for #ident {
+            type Params = #params;
+            type Codec = #codec;
+            const TEMPLATE: &'static str = #channel;
+
+            fn params(&self) -> Self::Params {
+                #params {
+                    #( #names: ::std::clone::Clone::clone(&self.#names), )*
+                }
+            }
+
+            fn channel(
+                params: &Self::Params,
+            ) -> ::redis_subscribe::Result<::std::string::String> {
+                ::redis_subscribe::topic::render(
+                    Self::TEMPLATE,
+                    &[ #( &params.#names as &dyn ::std::fmt::Display ),* ],
+                )
+            }
+
+            fn parse_channel(channel: &str) -> ::std::option::Option<Self::Params> {
+                let values = ::redis_subscribe::topic::parse(Self::TEMPLATE, channel)?;
+                ::std::option::Option::Some(#params {
+                    #( #names: values[#indices].parse().ok()?, )*
+                })
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proc_macro2::Span;
+
+    #[test]
+    fn template_params() {
+        let template = LitStr::new("orders.{region}.{id}", Span::call_site());
+        assert_eq!(params(&template).unwrap(), ["region", "id"]);
+
+        for template in ["{id}.{id}", "{a}{b}", "{a", "{}", "{a-b}"] {
+            let template = LitStr::new(template, Span::call_site());
+            assert!(
+                params(&template).is_err(),
+                "{} was accepted",
+                template.value()
+            );
+        }
+    }
+}
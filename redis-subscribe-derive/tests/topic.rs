@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use redis_subscribe::codec::Json;
+use redis_subscribe::{RedisSub, Topic};
+use serde::{Deserialize, Serialize};
+
+#[derive(Topic, Serialize, Deserialize, Debug, PartialEq)]
+#[topic(channel = "orders.{region}.{id}", codec = Json)]
+struct OrderCreated {
+    region: String,
+    id: u64,
+    amount: u32,
+}
+
+#[test]
+fn channel() {
+    let params = OrderCreatedParams {
+        region: "eu".to_string(),
+        id: 42,
+    };
+    assert_eq!(OrderCreated::channel(&params).unwrap(), "orders.eu.42");
+    assert_eq!(OrderCreated::parse_channel("orders.eu.42"), Some(params));
+    assert_eq!(OrderCreated::parse_channel("orders.eu.x"), None);
+    assert_eq!(OrderCreated::pattern(), "orders.*.*");
+
+    let order = OrderCreated {
+        region: "us".to_string(),
+        id: 7,
+        amount: 3,
+    };
+    let (channel, payload) = order.encode().unwrap();
+    assert_eq!(channel, "orders.us.7");
+    assert_eq!(payload, br#"{"region":"us","id":7,"amount":3}"#);
+
+    // Parameters which could not be parsed back out of the channel are rejected.
+    let params = OrderCreatedParams {
+        region: "e.u".to_string(),
+        id: 42,
+    };
+    assert!(OrderCreated::channel(&params).is_err());
+}
+
+#[tokio::test]
+async fn test_topic_psubscription() {
+    let client = redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+    let mut connection = client
+        .get_tokio_connection()
+        .await
+        .expect("failed to open Redis connection");
+
+    let (handle, _receiver) = RedisSub::new("127.0.0.1:6379")
+        .spawn()
+        .await
+        .expect("failed to connect to redis");
+    let mut subscription = handle
+        .topic_psubscription::<OrderCreated>()
+        .await
+        .expect("failed to subscribe to topic");
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let order = OrderCreated {
+        region: "eu".to_string(),
+        id: 42,
+        amount: 3,
+    };
+    let (channel, payload) = order.encode().unwrap();
+    // Matches the pattern, but not the template.
+    connection
+        .publish::<&str, &[u8], u32>("orders.eu.4.2", &payload)
+        .await
+        .expect("failed to send publish command to Redis");
+    connection
+        .publish::<&str, &[u8], u32>(&channel, &payload)
+        .await
+        .expect("failed to send publish command to Redis");
+
+    let (params, received) = tokio::time::timeout(Duration::from_secs(2), subscription.recv())
+        .await
+        .expect("timeout duration of 2 seconds was exceeded")
+        .expect("expected a value")
+        .expect("failed to decode value");
+    assert_eq!(params, order.params());
+    assert_eq!(received, order);
+}
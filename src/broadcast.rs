@@ -0,0 +1,94 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::Stream;
+
+use crate::Message;
+
+/// Receives a copy of every message yielded by the stream of a [RedisSub].
+///
+/// Created by [RedisSub::broadcast_receiver].
+/// A receiver which falls behind by more than the broadcast capacity misses the oldest messages,
+/// this is reported by a [Message::Lagged] with the amount of missed messages.
+///
+/// [RedisSub]: crate::RedisSub
+/// [RedisSub::broadcast_receiver]: crate::RedisSub::broadcast_receiver
+#[derive(Debug)]
+pub struct BroadcastReceiver {
+    stream: BroadcastStream<Message>,
+}
+
+impl BroadcastReceiver {
+    pub(crate) fn new(rx: broadcast::Receiver<Message>) -> Self {
+        Self {
+            stream: BroadcastStream::new(rx),
+        }
+    }
+}
+
+impl Stream for BroadcastReceiver {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        Pin::new(&mut self.stream).poll_next(cx).map(|res| {
+            res.map(|res| match res {
+                Ok(msg) => msg,
+                Err(BroadcastStreamRecvError::Lagged(n)) => Message::Lagged(n),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::RedisSub;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_broadcast_lagged() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let sub = RedisSub::new("127.0.0.1:6379").with_broadcast_capacity(2);
+        sub.subscribe("lagging".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        let mut broadcast = sub.broadcast_receiver();
+        let (_handle, mut receiver) = sub.spawn().await.expect("failed to connect to redis");
+
+        for _ in 0..2 {
+            receiver.recv().await.expect("expected a Message");
+        }
+        for i in 0..4 {
+            connection
+                .publish::<&str, String, u32>("lagging", i.to_string())
+                .await
+                .expect("failed to send publish command to Redis");
+        }
+        for _ in 0..4 {
+            receiver.recv().await.expect("expected a Message");
+        }
+
+        let msg = tokio::time::timeout(Duration::from_secs(2), broadcast.next())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message");
+        assert!(
+            matches!(msg, crate::Message::Lagged(4)),
+            "first broadcast message was not `Lagged(4)`: {:?}",
+            msg
+        );
+        match broadcast.next().await.expect("expected a Message") {
+            crate::Message::Message { message, .. } => assert_eq!(message, "2"),
+            msg => panic!("broadcast message was not `Message`: {:?}", msg),
+        }
+    }
+}
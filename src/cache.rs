@@ -0,0 +1,435 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::RwLock;
+
+use crate::{Invalidation, Message};
+
+/// Amount of invalidation epochs kept, keys sharing an epoch invalidate each other's reads.
+const EPOCH_BUCKETS: usize = 1024;
+
+/// Evicts cached values when invalidations are received, see [RedisSub::with_cache].
+///
+/// [RedisSub::with_cache]: crate::RedisSub::with_cache
+pub(crate) trait Evict: Debug + Send + Sync {
+    /// The channels on which invalidated keys are published.
+    fn channels(&self) -> &[String];
+
+    /// Evict the values invalidated by a message.
+    fn evict(&self, msg: &Message);
+}
+
+/// Position in the invalidations of a cache, taken with `.epoch()` before reading a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CacheEpoch(u64);
+
+/// Local cache of values read from Redis, kept coherent by invalidation messages.
+///
+/// Register the cache with [RedisSub::with_cache], after which the stream evicts:
+///
+/// - the keys of [Message::Invalidation]s, see [RedisSub::with_tracking_invalidation],
+/// - the keys published as messages on the channels added with `.with_channel()`,
+/// - every key on [Message::Disconnected], as invalidations may have been missed,
+///   and again on the [Message::Gap] after reconnecting.
+///
+/// Take an [CacheEpoch] with `.epoch()` before reading a value from Redis,
+/// and pass it to `.insert()`, which drops the value if the key was invalidated since.
+///
+/// Inserted values are only cached while invalidations are received:
+/// a cache with channels waits until all of them are subscribed to,
+/// after connecting and after the connection was lost.
+/// A cache without channels relies on tracking, which must be redirected to every new connection,
+/// so it waits until `.confirm_tracking()` is called or an invalidation is received.
+///
+/// Invalidations are only received while the stream returned by `.listen()` is polled.
+///
+/// [RedisSub::with_cache]: crate::RedisSub::with_cache
+/// [RedisSub::with_tracking_invalidation]: crate::RedisSub::with_tracking_invalidation
+pub struct InvalidatingCache<K, V> {
+    /// The cached values and invalidation state.
+    state: RwLock<State<K, V>>,
+    /// Channels on which invalidated keys are published.
+    channels: Vec<String>,
+    /// Hasher picking the epoch of a key.
+    hasher: RandomState,
+}
+
+/// State of a cache, changed at once so reads and invalidations do not interleave.
+struct State<K, V> {
+    /// The cached values.
+    values: HashMap<K, V>,
+    /// CacheEpoch of the last invalidation.
+    epoch: u64,
+    /// CacheEpoch of the last invalidation of the keys, by bucket.
+    invalidated: Vec<u64>,
+    /// CacheEpoch of the last invalidation of every key.
+    cleared: u64,
+    /// Channels which are not subscribed to since connecting.
+    unsubscribed: HashSet<String>,
+    /// Whether tracking is not confirmed since connecting.
+    untracked: bool,
+}
+
+impl<K, V> State<K, V>
+where
+    K: Borrow<str> + Eq + Hash,
+{
+    /// Remove a value, rejecting the reads of the key before it.
+    fn invalidate(&mut self, key: &str, bucket: usize) -> Option<V> {
+        self.epoch += 1;
+        self.invalidated[bucket] = self.epoch;
+        self.values.remove(key)
+    }
+
+    /// Remove every value, rejecting all reads before it.
+    fn clear(&mut self) {
+        self.epoch += 1;
+        self.cleared = self.epoch;
+        self.values.clear();
+    }
+}
+
+impl<K, V> InvalidatingCache<K, V>
+where
+    K: Borrow<str> + Eq + Hash,
+{
+    /// Create an empty cache.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: RwLock::new(State {
+                values: HashMap::new(),
+                epoch: 0,
+                invalidated: vec![0; EPOCH_BUCKETS],
+                cleared: 0,
+                unsubscribed: HashSet::new(),
+                untracked: true,
+            }),
+            channels: Vec::new(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Evict the key published as a message on the channel.
+    ///
+    /// The channel is subscribed to by [RedisSub::with_cache].
+    ///
+    /// [RedisSub::with_cache]: crate::RedisSub::with_cache
+    #[must_use]
+    pub fn with_channel(mut self, channel: &str) -> Self {
+        if !self.channels.iter().any(|c| c == channel) {
+            self.channels.push(channel.to_string());
+            self.state
+                .get_mut()
+                .unwrap()
+                .unsubscribed
+                .insert(channel.to_string());
+        }
+        self
+    }
+
+    /// The bucket holding the invalidation epoch of a key.
+    fn bucket(&self, key: &str) -> usize {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        (hasher.finish() % EPOCH_BUCKETS as u64) as usize
+    }
+
+    /// Confirm that tracking is redirected to the current connection,
+    /// after sending `CLIENT TRACKING on REDIRECT <id>` with the ID of [Message::Connected].
+    ///
+    /// A cache without channels does not cache values after connecting until this is called,
+    /// or an invalidation is received.
+    pub fn confirm_tracking(&self) {
+        self.state.write().unwrap().untracked = false;
+    }
+
+    /// The epoch to pass to `.insert()`, taken before reading a value.
+    #[must_use]
+    pub fn epoch(&self) -> CacheEpoch {
+        CacheEpoch(self.state.read().unwrap().epoch)
+    }
+
+    /// The cached value of a key.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.state.read().unwrap().values.get(key).cloned()
+    }
+
+    /// Cache the value of a key read after taking the epoch, returning whether it was cached.
+    ///
+    /// The value is not cached if the key was invalidated after the epoch was taken,
+    /// or while invalidations are not received.
+    pub fn insert(&self, key: K, value: V, epoch: CacheEpoch) -> bool {
+        let bucket = self.bucket(key.borrow());
+        let mut state = self.state.write().unwrap();
+        let receiving =
+            state.unsubscribed.is_empty() && !(self.channels.is_empty() && state.untracked);
+        if !receiving || state.cleared > epoch.0 || state.invalidated[bucket] > epoch.0 {
+            return false;
+        }
+
+        state.values.insert(key, value);
+        true
+    }
+
+    /// Remove the cached value of a key.
+    ///
+    /// Reads of the key before this are not cached.
+    pub fn remove(&self, key: &str) -> Option<V> {
+        let bucket = self.bucket(key);
+        self.state.write().unwrap().invalidate(key, bucket)
+    }
+
+    /// Remove every cached value.
+    ///
+    /// Reads before this are not cached.
+    pub fn clear(&self) {
+        self.state.write().unwrap().clear();
+    }
+
+    /// The amount of cached values.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.state.read().unwrap().values.len()
+    }
+
+    /// Whether no values are cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.state.read().unwrap().values.is_empty()
+    }
+}
+
+impl<K, V> Default for InvalidatingCache<K, V>
+where
+    K: Borrow<str> + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Debug for InvalidatingCache<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.read().unwrap();
+        f.debug_struct("InvalidatingCache")
+            .field("len", &state.values.len())
+            .field("channels", &self.channels)
+            .field("epoch", &state.epoch)
+            .field("unsubscribed", &state.unsubscribed)
+            .field("untracked", &state.untracked)
+            .finish()
+    }
+}
+
+impl<K, V> Evict for InvalidatingCache<K, V>
+where
+    K: Borrow<str> + Eq + Hash + Send + Sync,
+    V: Send + Sync,
+{
+    fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    fn evict(&self, msg: &Message) {
+        match msg {
+            // Invalidations are only received once tracking is redirected to the connection.
+            Message::Invalidation {
+                invalidation: Invalidation::Keys(keys),
+                ..
+            } => {
+                let mut state = self.state.write().unwrap();
+                for key in keys {
+                    state.invalidate(key, self.bucket(key));
+                }
+                state.untracked = false;
+            }
+            Message::Message {
+                channel, message, ..
+            } if self.channels.contains(channel) => {
+                self.remove(message);
+            }
+            // Invalidations will not be received until the channels are subscribed to,
+            // and tracking is redirected to the new connection.
+            Message::Disconnected(_) | Message::Fatal(_) | Message::Closed => {
+                let mut state = self.state.write().unwrap();
+                state.unsubscribed = self.channels.iter().cloned().collect();
+                state.untracked = true;
+                state.clear();
+            }
+            Message::Subscription { channel, .. } => {
+                self.state.write().unwrap().unsubscribed.remove(channel);
+            }
+            // Invalidations may have been missed.
+            Message::Invalidation {
+                invalidation: Invalidation::Flush,
+                ..
+            }
+            | Message::Gap { .. } => self.clear(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, Metadata, RedisSub, INVALIDATE_CHANNEL};
+    use redis::AsyncCommands;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn evict() {
+        let cache = InvalidatingCache::new();
+        cache.confirm_tracking();
+        let epoch = cache.epoch();
+        cache.insert("user:1".to_string(), 1, epoch);
+        cache.insert("user:2".to_string(), 2, epoch);
+        cache.insert("user:3".to_string(), 3, epoch);
+
+        cache.evict(&Message::Invalidation {
+            invalidation: Invalidation::Keys(vec!["user:1".to_string()]),
+            metadata: Metadata::test(),
+        });
+        assert_eq!(cache.get("user:1"), None);
+        assert_eq!(cache.get("user:2"), Some(2));
+
+        // Messages on other channels are kept.
+        cache.evict(&Message::Message {
+            channel: "other".to_string(),
+            message: "user:2".to_string(),
+            metadata: Metadata::test(),
+        });
+        assert_eq!(cache.len(), 2);
+
+        cache.evict(&Message::Disconnected(Error::ZeroBytesRead));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn read_before_invalidation() {
+        let cache = InvalidatingCache::new();
+        cache.confirm_tracking();
+
+        // A value read before its key was invalidated is stale.
+        let epoch = cache.epoch();
+        cache.evict(&Message::Invalidation {
+            invalidation: Invalidation::Keys(vec!["user:1".to_string()]),
+            metadata: Metadata::test(),
+        });
+        assert!(!cache.insert("user:1".to_string(), 1, epoch));
+        assert!(cache.is_empty());
+
+        // Reads after the invalidation are cached.
+        assert!(cache.insert("user:1".to_string(), 1, cache.epoch()));
+
+        let epoch = cache.epoch();
+        cache.clear();
+        assert!(!cache.insert("user:2".to_string(), 2, epoch));
+    }
+
+    #[test]
+    fn reconnect() {
+        let cache = InvalidatingCache::new()
+            .with_channel("invalidations")
+            .with_channel("invalidations");
+
+        // Values are not cached until the channel is subscribed to.
+        assert!(!cache.insert("user:1".to_string(), 1, cache.epoch()));
+        cache.evict(&Message::Subscription {
+            channel: "invalidations".to_string(),
+            subscriptions: 1,
+        });
+        assert!(cache.insert("user:1".to_string(), 1, cache.epoch()));
+
+        // Or again after the connection was lost.
+        cache.evict(&Message::Disconnected(Error::ZeroBytesRead));
+        cache.evict(&Message::Connected { client_id: None });
+        assert!(!cache.insert("user:1".to_string(), 1, cache.epoch()));
+        assert!(cache.is_empty());
+
+        let epoch = cache.epoch();
+        cache.evict(&Message::Subscription {
+            channel: "invalidations".to_string(),
+            subscriptions: 1,
+        });
+        assert!(cache.insert("user:1".to_string(), 1, epoch));
+        assert_eq!(cache.get("user:1"), Some(1));
+
+        cache.evict(&Message::Gap {
+            channels: vec!["invalidations".to_string()],
+            patterns: Vec::new(),
+            since: SystemTime::now(),
+            until: SystemTime::now(),
+        });
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn reconnect_tracking() {
+        let cache = InvalidatingCache::new();
+        cache.confirm_tracking();
+        cache.evict(&Message::Disconnected(Error::ZeroBytesRead));
+
+        // Values are not cached until tracking is redirected to the new connection.
+        cache.evict(&Message::Connected { client_id: Some(8) });
+        cache.evict(&Message::Subscription {
+            channel: INVALIDATE_CHANNEL.to_string(),
+            subscriptions: 1,
+        });
+        assert!(!cache.insert("user:1".to_string(), 1, cache.epoch()));
+
+        cache.confirm_tracking();
+        assert!(cache.insert("user:1".to_string(), 1, cache.epoch()));
+
+        // Or until an invalidation is received.
+        cache.evict(&Message::Disconnected(Error::ZeroBytesRead));
+        cache.evict(&Message::Invalidation {
+            invalidation: Invalidation::Keys(vec!["user:2".to_string()]),
+            metadata: Metadata::test(),
+        });
+        assert!(cache.insert("user:1".to_string(), 1, cache.epoch()));
+    }
+
+    #[tokio::test]
+    async fn test_cache_channel() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let cache = Arc::new(InvalidatingCache::new().with_channel("cache:invalidate"));
+        let sub = RedisSub::new("127.0.0.1:6379").with_cache(cache.clone());
+        let mut stream = sub.listen().await.expect("failed to connect to redis");
+        for _ in 0..2 {
+            stream.next().await.expect("expected a Message");
+        }
+
+        let epoch = cache.epoch();
+        assert!(cache.insert("user:1".to_string(), "alice".to_string(), epoch));
+        assert!(cache.insert("user:2".to_string(), "bob".to_string(), epoch));
+
+        connection
+            .publish::<&str, &str, u32>("cache:invalidate", "user:1")
+            .await
+            .expect("failed to send publish command to Redis");
+        let msg = tokio::time::timeout(Duration::from_secs(2), stream.next())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message");
+        assert!(msg.is_message(), "message was not `Message`: {:?}", msg);
+
+        assert_eq!(cache.get("user:1"), None);
+        assert_eq!(cache.get("user:2"), Some("bob".to_string()));
+    }
+}
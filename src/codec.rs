@@ -0,0 +1,185 @@
+//! Codecs encoding and decoding the payloads of [TypedChannel]s.
+//!
+//! [Text] is always available, the others are enabled by the cargo feature of the same name:
+//! `json`, `msgpack`, `bincode` and `protobuf`.
+//!
+//! [TypedChannel]: crate::TypedChannel
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Error returned by a codec.
+pub type CodecError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Encodes values to payloads, and decodes payloads to values.
+pub trait Codec<T>: Send + Sync {
+    /// Encode a value to a payload which can be published.
+    ///
+    /// # Errors
+    /// Returns an error if the value cannot be encoded.
+    fn encode(&self, value: &T) -> Result<Vec<u8>, CodecError>;
+
+    /// Decode the payload of a received message.
+    ///
+    /// # Errors
+    /// Returns an error if the payload is not a valid encoding of a value.
+    fn decode(&self, payload: &[u8]) -> Result<T, CodecError>;
+}
+
+/// Payloads as UTF-8 text, with [Display] and [FromStr].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Text;
+
+impl<T> Codec<T> for Text
+where
+    T: Display + FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(value.to_string().into_bytes())
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<T, CodecError> {
+        Ok(std::str::from_utf8(payload)?.parse()?)
+    }
+}
+
+/// Payloads as JSON, with `serde`.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl<T> Codec<T> for Json
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<T, CodecError> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+}
+
+/// Payloads as MessagePack, with `serde`.
+///
+/// Structs are encoded as maps, so fields can be added and reordered.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl<T> Codec<T> for MessagePack
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<T, CodecError> {
+        Ok(rmp_serde::from_slice(payload)?)
+    }
+}
+
+/// Payloads as bincode, with `serde`.
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl<T> Codec<T> for Bincode
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<T, CodecError> {
+        Ok(bincode::deserialize(payload)?)
+    }
+}
+
+/// Payloads as protocol buffers, with `prost`.
+#[cfg(feature = "protobuf")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Protobuf;
+
+#[cfg(feature = "protobuf")]
+impl<T> Codec<T> for Protobuf
+where
+    T: prost::Message + Default,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(value.encode_to_vec())
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<T, CodecError> {
+        Ok(T::decode(payload)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode and decode a value with a codec.
+    fn round_trip<T, C: Codec<T>>(codec: C, value: &T) -> T {
+        let payload = codec.encode(value).expect("failed to encode");
+        codec.decode(&payload).expect("failed to decode")
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(round_trip(Text, &42u32), 42);
+        assert!(Codec::<u32>::decode(&Text, b"nope").is_err());
+        assert!(Codec::<u32>::decode(&Text, &[0xff]).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        let value = ("order".to_string(), 7u32);
+        assert_eq!(round_trip(Json, &value), value);
+        assert_eq!(Json.encode(&value).unwrap(), br#"["order",7]"#);
+        assert!(Codec::<(String, u32)>::decode(&Json, b"{").is_err());
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack() {
+        let value = ("order".to_string(), 7u32);
+        assert_eq!(round_trip(MessagePack, &value), value);
+        assert!(Codec::<(String, u32)>::decode(&MessagePack, &[0xc1]).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode() {
+        let value = ("order".to_string(), 7u32);
+        assert_eq!(round_trip(Bincode, &value), value);
+        assert!(Codec::<(String, u32)>::decode(&Bincode, &[1]).is_err());
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn protobuf() {
+        #[derive(Clone, PartialEq, prost::Message)]
+        struct Order {
+            #[prost(string, tag = "1")]
+            name: String,
+            #[prost(uint32, tag = "2")]
+            amount: u32,
+        }
+
+        let value = Order {
+            name: "order".to_string(),
+            amount: 7,
+        };
+        assert_eq!(round_trip(Protobuf, &value), value);
+        assert!(Codec::<Order>::decode(&Protobuf, &[0xff]).is_err());
+    }
+}
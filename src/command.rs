@@ -1,4 +1,4 @@
-use std::fmt::{Display, Formatter};
+use crate::resp::Response;
 
 #[derive(Debug)]
 pub enum Command {
@@ -6,29 +6,21 @@ pub enum Command {
     Unsubscribe(String),
     PatternSubscribe(String),
     PatternUnsubscribe(String),
+    UnsubscribeAll,
+    PatternUnsubscribeAll,
+    Quit,
 }
 
-impl Display for Command {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let d = match self {
-            Command::Subscribe(t) => {
-                f.write_str("SUBSCRIBE ")?;
-                t
-            }
-            Command::Unsubscribe(t) => {
-                f.write_str("UNSUBSCRIBE ")?;
-                t
-            }
-            Command::PatternSubscribe(t) => {
-                f.write_str("PSUBSCRIBE ")?;
-                t
-            }
-            Command::PatternUnsubscribe(t) => {
-                f.write_str("PUNSUBSCRIBE ")?;
-                t
-            }
-        };
-        f.write_str(d)?;
-        f.write_str("\r\n")
+impl From<&Command> for Response {
+    fn from(command: &Command) -> Self {
+        match command {
+            Command::Subscribe(t) => Response::command(["SUBSCRIBE", t]),
+            Command::Unsubscribe(t) => Response::command(["UNSUBSCRIBE", t]),
+            Command::PatternSubscribe(t) => Response::command(["PSUBSCRIBE", t]),
+            Command::PatternUnsubscribe(t) => Response::command(["PUNSUBSCRIBE", t]),
+            Command::UnsubscribeAll => Response::command(["UNSUBSCRIBE"]),
+            Command::PatternUnsubscribeAll => Response::command(["PUNSUBSCRIBE"]),
+            Command::Quit => Response::command(["QUIT"]),
+        }
     }
 }
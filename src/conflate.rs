@@ -0,0 +1,396 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::time::{sleep_until, Instant, Sleep};
+use tokio_stream::Stream;
+
+use crate::{glob, Message};
+
+/// How the messages of a channel are delayed before they are delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Window {
+    /// Deliver the latest message once no message was received for the duration.
+    Debounce(Duration),
+    /// Deliver at most one message per duration, the latest one.
+    Throttle(Duration),
+}
+
+/// Configuration of a [Conflated] stream, with the windows of the channels.
+///
+/// Messages of channels without a window are delivered as soon as the consumer polls,
+/// only the latest one if multiple were received since the last poll.
+/// Windows set for a channel take precedence over the windows of patterns,
+/// which are tried in the order they are added.
+#[derive(Debug, Clone, Default)]
+pub struct Conflation {
+    /// Windows of channels.
+    channels: HashMap<String, Window>,
+    /// Windows of patterns of channels.
+    patterns: Vec<(String, Window)>,
+}
+
+impl Conflation {
+    /// Create a configuration without any windows.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only deliver the latest message of a channel once no message was received on it for the window.
+    #[must_use]
+    pub fn debounce(mut self, channel: &str, window: Duration) -> Self {
+        self.channels
+            .insert(channel.to_string(), Window::Debounce(window));
+        self
+    }
+
+    /// Debounce the channels matching a pattern, see [glob].
+    #[must_use]
+    pub fn debounce_pattern(mut self, pattern: &str, window: Duration) -> Self {
+        self.patterns
+            .push((pattern.to_string(), Window::Debounce(window)));
+        self
+    }
+
+    /// Deliver at most one message of a channel per window.
+    ///
+    /// The first message is delivered right away,
+    /// the latest message received during the window once it has passed.
+    #[must_use]
+    pub fn throttle(mut self, channel: &str, window: Duration) -> Self {
+        self.channels
+            .insert(channel.to_string(), Window::Throttle(window));
+        self
+    }
+
+    /// Throttle the channels matching a pattern, see [glob].
+    #[must_use]
+    pub fn throttle_pattern(mut self, pattern: &str, window: Duration) -> Self {
+        self.patterns
+            .push((pattern.to_string(), Window::Throttle(window)));
+        self
+    }
+
+    /// The window of a channel, if any.
+    fn window(&self, channel: &str) -> Option<Window> {
+        self.channels.get(channel).copied().or_else(|| {
+            self.patterns
+                .iter()
+                .find(|(pattern, _)| glob::matches(pattern, channel))
+                .map(|(_, window)| *window)
+        })
+    }
+}
+
+/// What a message is conflated by: the pattern it matched, if any, and its channel.
+type Key = (Option<String>, String);
+
+/// The conflation key of a message, or `None` for messages without a channel.
+fn key(msg: &Message) -> Option<Key> {
+    match msg {
+        Message::Message { channel, .. } => Some((None, channel.clone())),
+        Message::PatternMessage {
+            pattern, channel, ..
+        } => Some((Some(pattern.clone()), channel.clone())),
+        Message::Binary {
+            pattern, channel, ..
+        } => Some((pattern.clone(), channel.clone())),
+        _ => None,
+    }
+}
+
+/// A message waiting to be delivered.
+#[derive(Debug)]
+enum Queued {
+    /// A message without a channel, like a control event, which is never conflated.
+    Event(Message),
+    /// The latest undelivered message of a channel.
+    Channel {
+        /// What the message is conflated by.
+        key: Key,
+        /// The message.
+        msg: Message,
+        /// When the message can be delivered.
+        due: Instant,
+    },
+}
+
+/// Stream of the latest messages of every channel, see [MessageStreamExt::conflate].
+///
+/// Every poll first takes all messages which are ready on the underlying stream,
+/// so messages are only conflated when the consumer falls behind.
+/// Messages are conflated per channel, and per pattern for the messages of pattern subscriptions.
+/// Messages are delivered in the order in which their channels first had a pending message,
+/// and messages without a channel, like control events, are delivered in order and never dropped:
+/// the messages received before such an event are delivered before it without waiting for their windows,
+/// and are not replaced by the messages received after it.
+/// Once the underlying stream ends, the pending messages are delivered without waiting for their windows.
+///
+/// [MessageStreamExt::conflate]: crate::MessageStreamExt::conflate
+#[derive(Debug)]
+pub struct Conflated<S> {
+    /// The stream of messages.
+    stream: S,
+    /// Windows of the channels.
+    conflation: Conflation,
+    /// The messages waiting to be delivered.
+    queue: VecDeque<Queued>,
+    /// When the windows of the recently throttled channels end.
+    throttled: HashMap<Key, Instant>,
+    /// Timer waking the stream when the next delayed message is due.
+    timer: Option<Pin<Box<Sleep>>>,
+    /// Whether the underlying stream has ended.
+    done: bool,
+}
+
+impl<S> Conflated<S> {
+    pub(crate) fn new(stream: S, conflation: Conflation) -> Self {
+        Self {
+            stream,
+            conflation,
+            queue: VecDeque::new(),
+            throttled: HashMap::new(),
+            timer: None,
+            done: false,
+        }
+    }
+
+    /// Queue a received message, replacing the pending message of its channel
+    /// unless an event was received since.
+    fn push(&mut self, msg: Message, now: Instant) {
+        let key = match key(&msg) {
+            Some(key) => key,
+            None => {
+                self.queue.push_back(Queued::Event(msg));
+                return;
+            }
+        };
+        let window = self.conflation.window(&key.1);
+
+        let pending = self
+            .queue
+            .iter_mut()
+            .rev()
+            .take_while(|queued| !matches!(queued, Queued::Event(_)))
+            .find_map(|queued| match queued {
+                Queued::Channel {
+                    key: pending,
+                    msg,
+                    due,
+                } if *pending == key => Some((msg, due)),
+                _ => None,
+            });
+        if let Some((pending, due)) = pending {
+            *pending = msg;
+            if let Some(Window::Debounce(window)) = window {
+                *due = now + window;
+            }
+            return;
+        }
+
+        let due = match window {
+            None => now,
+            Some(Window::Debounce(window)) => now + window,
+            Some(Window::Throttle(_)) => {
+                self.throttled.get(&key).map_or(now, |end| (*end).max(now))
+            }
+        };
+        self.queue.push_back(Queued::Channel { key, msg, due });
+    }
+
+    /// Take the first message which can be delivered.
+    fn pop(&mut self, now: Instant) -> Option<Message> {
+        // Everything before an event is delivered first, so the first message is.
+        let flush = self.done
+            || self
+                .queue
+                .iter()
+                .any(|queued| matches!(queued, Queued::Event(_)));
+        let index = if flush {
+            0
+        } else {
+            self.queue.iter().position(|queued| match queued {
+                Queued::Event(_) => true,
+                Queued::Channel { due, .. } => *due <= now,
+            })?
+        };
+
+        match self.queue.remove(index)? {
+            Queued::Event(msg) => Some(msg),
+            Queued::Channel { key, msg, .. } => {
+                if let Some(Window::Throttle(window)) = self.conflation.window(&key.1) {
+                    self.throttled.retain(|_, end| *end > now);
+                    self.throttled.insert(key, now + window);
+                }
+                Some(msg)
+            }
+        }
+    }
+
+    /// When the first delayed message is due.
+    fn next_due(&self) -> Option<Instant> {
+        self.queue
+            .iter()
+            .filter_map(|queued| match queued {
+                Queued::Event(_) => None,
+                Queued::Channel { due, .. } => Some(*due),
+            })
+            .min()
+    }
+}
+
+impl<S> Stream for Conflated<S>
+where
+    S: Stream<Item = Message> + Unpin,
+{
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        let this = &mut *self;
+
+        // Take everything the consumer has fallen behind on.
+        while !this.done {
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(msg)) => this.push(msg, Instant::now()),
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            if let Some(msg) = this.pop(Instant::now()) {
+                return Poll::Ready(Some(msg));
+            }
+            if this.queue.is_empty() {
+                return if this.done {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Pending
+                };
+            }
+
+            // Only delayed messages are left, so wait for the first one.
+            let due = this.next_due().expect("only delayed messages are queued");
+            let timer = this.timer.get_or_insert_with(|| Box::pin(sleep_until(due)));
+            timer.as_mut().reset(due);
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MessageStreamExt, Metadata};
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_stream::StreamExt;
+
+    /// The channel and payload of a message, or `None` for other messages.
+    fn content(msg: &Message) -> Option<(String, String)> {
+        match msg {
+            Message::Message {
+                channel, message, ..
+            } => Some((channel.clone(), message.clone())),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn latest_only() {
+        let stream = tokio_stream::iter(vec![
+            Message::Connected { client_id: None },
+            Message::test("prices", "1"),
+            Message::test("presence", "a"),
+            Message::test("prices", "2"),
+            Message::Closed,
+        ]);
+        let messages = stream.conflate(Conflation::new()).collect::<Vec<_>>().await;
+
+        assert_eq!(messages.len(), 4);
+        assert!(messages[0].is_connected());
+        assert_eq!(
+            content(&messages[1]),
+            Some(("prices".to_string(), "2".to_string()))
+        );
+        assert_eq!(
+            content(&messages[2]),
+            Some(("presence".to_string(), "a".to_string()))
+        );
+        assert!(messages[3].is_closed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn windows() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut stream = UnboundedReceiverStream::new(rx).conflate(
+            Conflation::new()
+                .debounce("presence", Duration::from_millis(100))
+                .throttle_pattern("prices.*", Duration::from_secs(1)),
+        );
+        let start = Instant::now();
+
+        // The first throttled message is delivered right away.
+        tx.send(Message::test("prices.eu", "1")).unwrap();
+        let msg = stream.next().await.unwrap();
+        assert_eq!(content(&msg).unwrap().1, "1");
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // The next ones are conflated until the window has passed.
+        tx.send(Message::test("prices.eu", "2")).unwrap();
+        tx.send(Message::test("presence", "a")).unwrap();
+        tx.send(Message::test("prices.eu", "3")).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tx.send(Message::test("presence", "b")).unwrap();
+
+        // The debounced message is delivered once the channel was quiet for its window.
+        let msg = stream.next().await.unwrap();
+        assert_eq!(content(&msg).unwrap().1, "b");
+        assert_eq!(start.elapsed(), Duration::from_millis(150));
+
+        let msg = stream.next().await.unwrap();
+        assert_eq!(content(&msg).unwrap().1, "3");
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        drop(tx);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn events_keep_order() {
+        let pattern_message = Message::PatternMessage {
+            pattern: "prices.*".to_string(),
+            channel: "prices.eu".to_string(),
+            message: "p".to_string(),
+            metadata: Metadata::test(),
+        };
+        let stream = tokio_stream::iter(vec![
+            Message::test("prices.eu", "1"),
+            pattern_message,
+            Message::Disconnected(crate::Error::ZeroBytesRead),
+            Message::test("prices.eu", "2"),
+        ]);
+        let mut stream =
+            stream.conflate(Conflation::new().debounce_pattern("prices.*", Duration::from_secs(1)));
+        let start = Instant::now();
+
+        // The messages before the event are delivered before it, without waiting.
+        assert_eq!(content(&stream.next().await.unwrap()).unwrap().1, "1");
+        assert!(matches!(
+            stream.next().await,
+            Some(Message::PatternMessage { .. })
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Message::Disconnected(_))
+        ));
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(content(&stream.next().await.unwrap()).unwrap().1, "2");
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -0,0 +1,343 @@
+use std::cmp;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::{thread_rng, Rng};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::sleep;
+
+use crate::resp::{Parser, Response};
+use crate::Limits;
+
+/// An owned dynamically typed future, as returned by a connection initializer.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A new connection to the server, before any channel is subscribed to.
+///
+/// Commands can be sent with [Connection::command], which waits for the reply.
+#[derive(Debug)]
+pub struct Connection {
+    read: OwnedReadHalf,
+    write: OwnedWriteHalf,
+    parser: Parser,
+}
+
+impl Connection {
+    pub(crate) fn new(read: OwnedReadHalf, write: OwnedWriteHalf, limits: Limits) -> Self {
+        Self {
+            read,
+            write,
+            parser: Parser::new(limits),
+        }
+    }
+
+    /// Split the connection into its parts, keeping the data which is read already.
+    pub(crate) fn into_parts(self) -> (OwnedReadHalf, OwnedWriteHalf, Parser) {
+        (self.read, self.write, self.parser)
+    }
+
+    /// Send a command and wait for the reply.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream,
+    /// or if the server replied with an error.
+    pub async fn command<I, A>(&mut self, args: I) -> crate::Result<Response>
+    where
+        I: IntoIterator<Item = A>,
+        A: AsRef<[u8]>,
+    {
+        self.request(&Response::command(args)).await
+    }
+
+    /// Send any value and wait for the reply.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream,
+    /// or if the server replied with an error.
+    pub async fn request(&mut self, request: &Response) -> crate::Result<Response> {
+        self.write.write_all(&request.to_bytes()?).await?;
+
+        match self.read_response().await? {
+            Response::Error(e) => Err(crate::Error::ServerError(e)),
+            res => Ok(res),
+        }
+    }
+
+    /// Get the ID the server assigned to this connection with `CLIENT ID`.
+    ///
+    /// Returns `None` if the server does not support the command.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn client_id(&mut self) -> crate::Result<Option<i64>> {
+        match self.command(["CLIENT", "ID"]).await {
+            Ok(Response::Integer(id)) => Ok(Some(id)),
+            Ok(res) => {
+                warn!("unexpected reply to CLIENT ID: {:?}", res);
+                Ok(None)
+            }
+            Err(crate::Error::ServerError(e)) => {
+                debug!("server does not support CLIENT ID: {}", e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read the next value from the server.
+    async fn read_response(&mut self) -> crate::Result<Response> {
+        let mut buf = [0; 4 * 1024];
+
+        loop {
+            if let Some(res) = self.parser.next_response()? {
+                return Ok(res);
+            }
+
+            match self.read.read(&mut buf).await? {
+                0 => return Err(crate::Error::ZeroBytesRead),
+                n => self.parser.feed(&buf[..n]),
+            }
+        }
+    }
+}
+
+/// Resolve the address and attempt a single connection.
+pub(crate) async fn connect(addr: &str) -> crate::Result<TcpStream> {
+    let addrs = match lookup_host(addr).await {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(e) if is_unknown_host(&e) => {
+            warn!("failed to resolve {}: {:?}", addr, e);
+            return Err(crate::Error::UnknownHost {
+                addr: addr.to_string(),
+                source: Some(Arc::new(e)),
+            });
+        }
+        Err(e) => {
+            warn!("failed to resolve {}, will retry: {:?}", addr, e);
+            return Err(e.into());
+        }
+    };
+    if addrs.is_empty() {
+        return Err(crate::Error::UnknownHost {
+            addr: addr.to_string(),
+            source: None,
+        });
+    }
+
+    Ok(TcpStream::connect(addrs.as_slice()).await?)
+}
+
+/// Whether the resolver confirmed that a host does not exist or has no addresses,
+/// unlike temporary failures like a resolver which cannot be reached.
+///
+/// The standard library only reports the message of `getaddrinfo`, so the messages are matched.
+fn is_unknown_host(e: &io::Error) -> bool {
+    // `WSAHOST_NOT_FOUND` and `WSANO_DATA` on Windows.
+    if cfg!(windows) && matches!(e.raw_os_error(), Some(11001 | 11004)) {
+        return true;
+    }
+
+    let message = e.to_string();
+    [
+        // `EAI_NONAME` and `EAI_NODATA` of glibc.
+        "Name or service not known",
+        "No address associated with hostname",
+        // `EAI_NONAME` of musl and macOS.
+        "Name does not resolve",
+        "nodename nor servname provided, or not known",
+    ]
+    .iter()
+    .any(|known| message.contains(known))
+}
+
+/// Connect to the server, retrying with exponential backoff.
+///
+/// Gives up after 8 attempts, after the first one if `fail_fast` is set,
+/// or once the retry policy does not retry an error.
+/// `on_attempt` is called before every attempt with the amount of attempts before it.
+pub(crate) async fn connect_with_backoff(
+    addr: &str,
+    fail_fast: bool,
+    retry_policy: &RetryPolicy,
+    mut on_attempt: impl FnMut(u32),
+) -> crate::Result<TcpStream> {
+    let mut retry_count = 0;
+
+    loop {
+        // Connect to the Redis server.
+        on_attempt(retry_count as u32);
+        let e = match connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => e,
+        };
+
+        if fail_fast || !retry_policy.retries(&e) || retry_count > 7 {
+            // Retry count has passed 7, or retrying will not help.
+            // Assume connection failed and return.
+            return Err(e);
+        }
+
+        // Backoff and reconnect.
+        warn!(
+            "failed to connect to redis (attempt {}/8) {:?}",
+            retry_count, e
+        );
+        retry_count += 1;
+        sleep(backoff(retry_count)).await;
+    }
+}
+
+/// Time to wait before the next connection attempt, with jitter.
+pub(crate) fn backoff(retry_count: u64) -> Duration {
+    let jitter = thread_rng().gen_range(0..1000);
+    Duration::from_millis(cmp::min(retry_count ^ 2, 64) * 1000 + jitter)
+}
+
+/// Decides whether to keep retrying after an error.
+#[derive(Clone)]
+pub(crate) struct RetryPolicy(Arc<dyn Fn(&crate::Error) -> bool + Send + Sync>);
+
+impl RetryPolicy {
+    pub(crate) fn new<F>(policy: F) -> Self
+    where
+        F: Fn(&crate::Error) -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(policy))
+    }
+
+    pub(crate) fn retries(&self, e: &crate::Error) -> bool {
+        (self.0)(e)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(|e| !e.is_fatal())
+    }
+}
+
+impl Debug for RetryPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RetryPolicy")
+    }
+}
+
+/// Function run on every new connection, before the stored channels are subscribed to.
+type InitFn = dyn for<'a> Fn(&'a mut Connection) -> BoxFuture<'a, crate::Result<()>> + Send + Sync;
+
+/// The registered connection initializers.
+#[derive(Default)]
+pub(crate) struct Initializers(Vec<Box<InitFn>>);
+
+impl Initializers {
+    pub(crate) fn push(&mut self, initializer: Box<InitFn>) {
+        self.0.push(initializer);
+    }
+
+    /// Run all initializers on the connection in order.
+    pub(crate) async fn run(&self, conn: &mut Connection) -> crate::Result<()> {
+        for initializer in &self.0 {
+            initializer(conn).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for Initializers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Initializers({})", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, RedisSub};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_initializer() {
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let recorded = names.clone();
+        let redis_sub = RedisSub::new("127.0.0.1:6379").with_initializer(move |conn| {
+            let recorded = recorded.clone();
+            Box::pin(async move {
+                conn.command(["CLIENT", "SETNAME", "initialized"]).await?;
+                let name = conn.command(["CLIENT", "GETNAME"]).await?;
+                recorded.lock().unwrap().push(name);
+                Ok(())
+            })
+        });
+
+        let mut stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+        let msg = tokio::time::timeout(Duration::from_secs(2), stream.next())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message");
+        assert!(msg.is_connected(), "message was not `Connected`: {:?}", msg);
+        assert_eq!(
+            *names.lock().unwrap(),
+            vec![Response::Bulk(b"initialized".to_vec())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_initializer_error_aborts_connection() {
+        let redis_sub = RedisSub::new("127.0.0.1:6379")
+            .with_initializer(|conn| {
+                Box::pin(async move {
+                    conn.command(["NOT-A-COMMAND"]).await?;
+                    Ok(())
+                })
+            })
+            .with_retry_policy(|_| false);
+
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+        let messages = tokio::time::timeout(Duration::from_secs(2), stream.collect::<Vec<_>>())
+            .await
+            .expect("stream did not end after giving up");
+
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [
+                    Message::Error(crate::Error::ServerError(_)),
+                    Message::Fatal(crate::Error::ServerError(_))
+                ]
+            ),
+            "unexpected messages after failed initialization: {:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn test_temporary_resolver_failure_is_retried() {
+        let unknown = io::Error::new(
+            io::ErrorKind::Other,
+            "failed to lookup address information: Name or service not known",
+        );
+        let temporary = io::Error::new(
+            io::ErrorKind::Other,
+            "failed to lookup address information: Temporary failure in name resolution",
+        );
+
+        assert!(is_unknown_host(&unknown));
+        assert!(!is_unknown_host(&temporary));
+        assert!(!crate::Error::from(temporary).is_fatal());
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::resp::Response;
+use crate::ParserError;
+
+/// ID of an entry in a Redis stream, like `1526919030474-55`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    /// Milliseconds part of the ID.
+    pub ms: u64,
+    /// Sequence number within the millisecond.
+    pub seq: u64,
+}
+
+impl StreamId {
+    /// The smallest ID greater than this one.
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self.seq.checked_add(1) {
+            Some(seq) => Self { ms: self.ms, seq },
+            None => Self {
+                ms: self.ms + 1,
+                seq: 0,
+            },
+        }
+    }
+}
+
+impl StreamId {
+    /// Parse an ID in the complete `<ms>-<seq>` form, as the server returns it.
+    ///
+    /// # Errors
+    /// Returns an error if the sequence number is missing or if a part is not a number.
+    pub(crate) fn parse_complete(s: &str) -> Result<Self, ParserError> {
+        let (ms, seq) = s.split_once('-').ok_or(ParserError::InvalidStreamId)?;
+
+        Ok(Self {
+            ms: ms.parse().map_err(|_| ParserError::InvalidStreamId)?,
+            seq: seq.parse().map_err(|_| ParserError::InvalidStreamId)?,
+        })
+    }
+}
+
+/// Parses an ID like a bound of `XRANGE`, where the sequence number defaults to zero.
+impl FromStr for StreamId {
+    type Err = ParserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('-') {
+            Some(_) => Self::parse_complete(s),
+            None => Ok(Self {
+                ms: s.parse().map_err(|_| ParserError::InvalidStreamId)?,
+                seq: 0,
+            }),
+        }
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// An entry read from a Redis stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEntry {
+    /// ID of the entry.
+    pub id: StreamId,
+    /// Field-value pairs of the entry, in order.
+    pub fields: Vec<(String, String)>,
+}
+
+impl StreamEntry {
+    /// The value of a field.
+    #[must_use]
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parse a list of entries, as returned by `XRANGE`, skipping deleted entries.
+    ///
+    /// # Errors
+    /// Returns an error if the response has unexpected types.
+    pub(crate) fn from_range(res: Response) -> crate::Result<Vec<Self>> {
+        Ok(Self::split_range(res)?.0)
+    }
+
+    /// Parse a list of entries, returning the entries and the IDs of the deleted entries.
+    ///
+    /// # Errors
+    /// Returns an error if the response has unexpected types.
+    fn split_range(res: Response) -> crate::Result<(Vec<Self>, Vec<StreamId>)> {
+        let mut entries = Vec::new();
+        let mut deleted = Vec::new();
+        let list = match res {
+            Response::Array(list) => list,
+            Response::Null | Response::NullArray => return Ok((entries, deleted)),
+            _ => return Err(ParserError::MalformedResponse.into()),
+        };
+
+        for entry in list {
+            match Self::from_response(entry)? {
+                (_, Some(entry)) => entries.push(entry),
+                (id, None) => deleted.push(id),
+            }
+        }
+        Ok((entries, deleted))
+    }
+
+    /// Parse the entries of every stream, as returned by `XREADGROUP`.
+    ///
+    /// # Errors
+    /// Returns an error if the response has unexpected types.
+    pub(crate) fn from_read(res: Response) -> crate::Result<Vec<StreamRead>> {
+        let streams = match res {
+            Response::Array(streams) => streams,
+            // No entries were added before the timeout.
+            Response::Null | Response::NullArray => return Ok(Vec::new()),
+            _ => return Err(ParserError::MalformedResponse.into()),
+        };
+
+        streams
+            .into_iter()
+            .map(|stream| match stream {
+                Response::Array(parts) if parts.len() == 2 => {
+                    let mut parts = parts.into_iter();
+                    let key = match parts.next() {
+                        Some(Response::Bulk(key)) => {
+                            String::from_utf8(key).map_err(|e| e.utf8_error())?
+                        }
+                        _ => return Err(ParserError::MalformedResponse.into()),
+                    };
+                    let (entries, deleted) =
+                        Self::split_range(parts.next().unwrap_or(Response::Null))?;
+
+                    Ok(StreamRead {
+                        key,
+                        entries,
+                        deleted,
+                    })
+                }
+                _ => Err(ParserError::MalformedResponse.into()),
+            })
+            .collect()
+    }
+
+    /// Parse the next cursor and the claimed entries, as returned by `XAUTOCLAIM`.
+    ///
+    /// # Errors
+    /// Returns an error if the response has unexpected types.
+    pub(crate) fn from_claimed(res: Response) -> crate::Result<(StreamId, Vec<Self>)> {
+        let mut parts = match res {
+            Response::Array(parts) if parts.len() >= 2 => parts.into_iter(),
+            _ => return Err(ParserError::MalformedResponse.into()),
+        };
+
+        let cursor = match parts.next() {
+            Some(Response::Bulk(cursor)) => std::str::from_utf8(&cursor)?.parse()?,
+            _ => return Err(ParserError::InvalidStreamId.into()),
+        };
+        let entries = Self::from_range(parts.next().unwrap_or(Response::Null))?;
+
+        Ok((cursor, entries))
+    }
+
+    /// Parse a single entry, an array of the ID and the field-value pairs.
+    ///
+    /// Returns the ID with `None` for entries which are deleted, and have no fields.
+    fn from_response(res: Response) -> crate::Result<(StreamId, Option<Self>)> {
+        let mut parts = match res {
+            Response::Array(parts) if parts.len() == 2 => parts.into_iter(),
+            _ => return Err(ParserError::MalformedResponse.into()),
+        };
+
+        let id = match parts.next() {
+            Some(Response::Bulk(id)) => std::str::from_utf8(&id)?.parse()?,
+            _ => return Err(ParserError::InvalidStreamId.into()),
+        };
+
+        let values = match parts.next() {
+            Some(Response::Array(values)) if values.len() % 2 == 0 => values,
+            Some(Response::Null | Response::NullArray) => return Ok((id, None)),
+            _ => return Err(ParserError::MalformedResponse.into()),
+        };
+
+        let mut fields = Vec::with_capacity(values.len() / 2);
+        let mut values = values.into_iter();
+        while let (Some(field), Some(value)) = (values.next(), values.next()) {
+            match (field, value) {
+                (Response::Bulk(field), Response::Bulk(value)) => fields.push((
+                    String::from_utf8(field).map_err(|e| e.utf8_error())?,
+                    String::from_utf8(value).map_err(|e| e.utf8_error())?,
+                )),
+                _ => return Err(ParserError::MalformedResponse.into()),
+            }
+        }
+
+        Ok((id, Some(Self { id, fields })))
+    }
+}
+
+/// The entries read from a stream, as returned by `XREADGROUP`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StreamRead {
+    /// Key of the stream.
+    pub(crate) key: String,
+    /// The entries which were read.
+    pub(crate) entries: Vec<StreamEntry>,
+    /// IDs of the pending entries which were deleted from the stream, and have no fields.
+    pub(crate) deleted: Vec<StreamId>,
+}
+
+impl StreamRead {
+    /// The ID of the last entry which was read, including the deleted entries.
+    pub(crate) fn last_id(&self) -> Option<StreamId> {
+        let last_entry = self.entries.last().map(|entry| entry.id);
+
+        last_entry.max(self.deleted.last().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_id() {
+        let id: StreamId = "1526919030474-55".parse().unwrap();
+        assert_eq!(
+            id,
+            StreamId {
+                ms: 1526919030474,
+                seq: 55
+            }
+        );
+        assert_eq!(id.to_string(), "1526919030474-55");
+        assert_eq!("12".parse(), Ok(StreamId { ms: 12, seq: 0 }));
+        assert_eq!(
+            StreamId::parse_complete("12"),
+            Err(ParserError::InvalidStreamId)
+        );
+        assert_eq!(
+            "12-x".parse::<StreamId>(),
+            Err(ParserError::InvalidStreamId)
+        );
+
+        assert_eq!(
+            id.next(),
+            StreamId {
+                ms: 1526919030474,
+                seq: 56
+            }
+        );
+        assert_eq!(
+            StreamId {
+                ms: 1,
+                seq: u64::MAX
+            }
+            .next(),
+            StreamId { ms: 2, seq: 0 }
+        );
+    }
+
+    #[test]
+    fn range() {
+        let res = Response::Array(vec![Response::Array(vec![
+            Response::Bulk(b"1-2".to_vec()),
+            Response::Array(vec![
+                Response::Bulk(b"message".to_vec()),
+                Response::Bulk(b"hello".to_vec()),
+            ]),
+        ])]);
+
+        let entries = StreamEntry::from_range(res).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, StreamId { ms: 1, seq: 2 });
+        assert_eq!(entries[0].get("message"), Some("hello"));
+        assert_eq!(entries[0].get("other"), None);
+    }
+
+    #[test]
+    fn read_deleted() {
+        let res = Response::Array(vec![Response::Array(vec![
+            Response::Bulk(b"jobs".to_vec()),
+            Response::Array(vec![
+                Response::Array(vec![Response::Bulk(b"1-1".to_vec()), Response::NullArray]),
+                Response::Array(vec![Response::Bulk(b"1-2".to_vec()), Response::NullArray]),
+            ]),
+        ])]);
+
+        // A batch of only deleted entries still moves past them.
+        let streams = StreamEntry::from_read(res).unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].key, "jobs");
+        assert!(streams[0].entries.is_empty());
+        assert_eq!(
+            streams[0].deleted,
+            [StreamId { ms: 1, seq: 1 }, StreamId { ms: 1, seq: 2 }]
+        );
+        assert_eq!(streams[0].last_id(), Some(StreamId { ms: 1, seq: 2 }));
+    }
+
+    #[test]
+    fn claimed() {
+        let res = Response::Array(vec![
+            Response::Bulk(b"0-0".to_vec()),
+            Response::Array(vec![
+                Response::Array(vec![
+                    Response::Bulk(b"1-1".to_vec()),
+                    Response::Array(vec![
+                        Response::Bulk(b"job".to_vec()),
+                        Response::Bulk(b"1".to_vec()),
+                    ]),
+                ]),
+                // Deleted entries have no fields.
+                Response::Array(vec![Response::Bulk(b"1-2".to_vec()), Response::Null]),
+            ]),
+            Response::Array(vec![]),
+        ]);
+
+        let (cursor, entries) = StreamEntry::from_claimed(res).unwrap();
+        assert_eq!(cursor, StreamId { ms: 0, seq: 0 });
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get("job"), Some("1"));
+    }
+}
@@ -1,13 +1,14 @@
-use std::{io, str::Utf8Error};
+use std::{io, str::Utf8Error, sync::Arc};
 
 use thiserror::Error;
 
 /// All possible errors returned by this library.
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
+#[non_exhaustive]
 pub enum Error {
     /// An IO error happened on the underlying TCP stream.
     #[error(transparent)]
-    IoError(#[from] io::Error),
+    IoError(Arc<io::Error>),
     /// An error happened while decoding the data from Redis as UTF-8.
     #[error(transparent)]
     Utf8Error(#[from] Utf8Error),
@@ -20,6 +21,68 @@ pub enum Error {
     /// Zero bytes were read from the TCP socket: this is an IO error and is usually fatal.
     #[error("No bytes are read from the socket, socket is closed.")]
     ZeroBytesRead,
+    /// The server replied with an error.
+    #[error("The server replied with an error: {0}")]
+    ServerError(String),
+    /// The host of the server does not exist, or has no addresses.
+    ///
+    /// Temporary resolver failures are returned as an [Error::IoError] instead.
+    #[error("Failed to resolve the address {addr}.")]
+    UnknownHost {
+        /// The address of the server.
+        addr: String,
+        /// The error of the resolver, if it returned one.
+        #[source]
+        source: Option<Arc<io::Error>>,
+    },
+    /// A stream consumer was started without any streams to read from.
+    #[error("No streams to read from.")]
+    NoStreams,
+    /// The hierarchical topic filter is invalid.
+    #[error("Invalid topic filter {0}.")]
+    InvalidFilter(String),
+    /// A simple string or error to encode contains `\r` or `\n`, which would end its line early.
+    #[error("Line to encode contains a line break: {0:?}.")]
+    InvalidLine(String),
+    /// The client name contains spaces or special characters.
+    #[error("Invalid client name {0:?}.")]
+    InvalidClientName(String),
+    /// The value of a channel template parameter is empty,
+    /// or contains the text following the parameter.
+    #[error("Invalid value {value:?} of channel parameter {name}.")]
+    InvalidParameter {
+        /// The name of the parameter.
+        name: String,
+        /// The value of the parameter.
+        value: String,
+    },
+}
+
+impl Error {
+    /// Whether retrying cannot resolve this error.
+    ///
+    /// Authentication and permission errors replied by the server are fatal,
+    /// as are hosts which do not exist, invalid addresses and stream consumers without streams.
+    #[must_use]
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::ServerError(e) => ["NOAUTH", "WRONGPASS", "NOPERM"]
+                .iter()
+                .any(|prefix| e.starts_with(prefix)),
+            Self::UnknownHost { .. } | Self::NoStreams => true,
+            Self::IoError(e) => matches!(
+                e.kind(),
+                io::ErrorKind::InvalidInput | io::ErrorKind::PermissionDenied
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::IoError(Arc::new(e))
+    }
 }
 
 /// An wrapper around the standard [Result] type with [Error] aliased to this crate's error type.
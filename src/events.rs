@@ -0,0 +1,188 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use crate::Error;
+
+/// Receives the connection lifecycle events of a [RedisSub].
+///
+/// All methods have empty default implementations.
+/// They are called from the stream returned by `.listen()` before the related message is yielded,
+/// so long running work should be moved to a separate task.
+///
+/// [RedisSub]: crate::RedisSub
+#[allow(unused_variables)]
+pub trait ConnectionListener: Send + Sync {
+    /// A connection attempt is started, `attempt` counts from zero for every reconnect.
+    ///
+    /// The connection made by `.listen()` to check the server is reachable is not reported.
+    fn on_connect_attempt(&self, attempt: u32) {}
+
+    /// A connection to the server is established.
+    fn on_connected(&self) {}
+
+    /// The stored channels and patterns are subscribed to again on a new connection.
+    fn on_subscriptions_restored(&self, channels: &[String], patterns: &[String]) {}
+
+    /// The connection to the server is lost.
+    fn on_disconnected(&self, reason: &Error) {}
+
+    /// Reconnecting is given up after an error, the stream ends.
+    fn on_give_up(&self, reason: &Error) {}
+}
+
+/// The registered connection listeners.
+#[derive(Clone, Default)]
+pub(crate) struct Listeners(Vec<Arc<dyn ConnectionListener>>);
+
+impl Listeners {
+    pub(crate) fn push(&mut self, listener: Arc<dyn ConnectionListener>) {
+        self.0.push(listener);
+    }
+
+    pub(crate) fn connect_attempt(&self, attempt: u32) {
+        self.0.iter().for_each(|l| l.on_connect_attempt(attempt));
+    }
+
+    pub(crate) fn connected(&self) {
+        self.0.iter().for_each(|l| l.on_connected());
+    }
+
+    pub(crate) fn subscriptions_restored(&self, channels: &[String], patterns: &[String]) {
+        self.0
+            .iter()
+            .for_each(|l| l.on_subscriptions_restored(channels, patterns));
+    }
+
+    pub(crate) fn disconnected(&self, reason: &Error) {
+        self.0.iter().for_each(|l| l.on_disconnected(reason));
+    }
+
+    pub(crate) fn give_up(&self, reason: &Error) {
+        self.0.iter().for_each(|l| l.on_give_up(reason));
+    }
+}
+
+impl Debug for Listeners {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Listeners({})", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RedisSub;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_stream::StreamExt;
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl ConnectionListener for Recorder {
+        fn on_connect_attempt(&self, attempt: u32) {
+            self.0.lock().unwrap().push(format!("attempt {}", attempt));
+        }
+
+        fn on_connected(&self) {
+            self.0.lock().unwrap().push("connected".to_string());
+        }
+
+        fn on_subscriptions_restored(&self, channels: &[String], _: &[String]) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("restored {:?}", channels));
+        }
+
+        fn on_disconnected(&self, _: &Error) {
+            self.0.lock().unwrap().push("disconnected".to_string());
+        }
+
+        fn on_give_up(&self, _: &Error) {
+            self.0.lock().unwrap().push("give up".to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_listener_events() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Reply to `CLIENT ID`, then close the connections.
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 64];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(b":7\r\n").await;
+            }
+        });
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let redis_sub = RedisSub::new(&addr)
+            .with_listener(Recorder(events.clone()))
+            .with_retry_policy(|_| false);
+        redis_sub
+            .subscribe("events".to_string())
+            .await
+            .expect("failed to subscribe to channel");
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to listener");
+        tokio::time::timeout(Duration::from_secs(2), stream.collect::<Vec<_>>())
+            .await
+            .expect("stream did not end after giving up");
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "attempt 0",
+                "connected",
+                "restored [\"events\"]",
+                "disconnected",
+                "give up"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_listener_initializer_error() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Reply to `CLIENT ID`, then keep the connections open.
+        tokio::spawn(async move {
+            let mut kept = Vec::new();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 64];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(b":7\r\n").await;
+                kept.push(socket);
+            }
+        });
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let redis_sub = RedisSub::new(&addr)
+            .with_listener(Recorder(events.clone()))
+            .with_initializer(|_| Box::pin(async { Err(Error::NotSubscribed) }))
+            .with_retry_policy(|_| false);
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to listener");
+        tokio::time::timeout(Duration::from_secs(2), stream.collect::<Vec<_>>())
+            .await
+            .expect("stream did not end after giving up");
+
+        // The connection is reported as lost when it cannot be initialized.
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["attempt 0", "connected", "disconnected", "give up"]
+        );
+    }
+}
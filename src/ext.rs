@@ -0,0 +1,250 @@
+use std::fmt::{Debug, Formatter};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio_stream::Stream;
+
+use crate::codec::Codec;
+use crate::{glob, typed, Conflated, Conflation, DecodeError, Message};
+
+/// Combinators for streams of [Message]s, like the stream returned by `.listen()`.
+///
+/// This is implemented for every [Unpin] stream of messages,
+/// pin other streams with [Box::pin] first.
+pub trait MessageStreamExt: Stream<Item = Message> + Unpin + Sized {
+    /// Keep only the channel and pattern messages, dropping the control events.
+    fn only_messages(self) -> MessageFilter<Self> {
+        MessageFilter::new(self, Filter::Messages)
+    }
+
+    /// Keep only the messages of a channel.
+    fn for_channel(self, channel: &str) -> MessageFilter<Self> {
+        MessageFilter::new(self, Filter::Channel(channel.to_string()))
+    }
+
+    /// Keep only the messages of the channels matching a pattern, see [glob].
+    fn matching(self, pattern: &str) -> MessageFilter<Self> {
+        MessageFilter::new(self, Filter::Pattern(pattern.to_string()))
+    }
+
+    /// Keep only the control events, like subscriptions, reconnects and errors.
+    ///
+    /// Stream entries and invalidations carry data, so they are not control events.
+    fn control_events(self) -> MessageFilter<Self> {
+        MessageFilter::new(self, Filter::Control)
+    }
+
+    /// Map the channel and pattern messages to their payloads, dropping the other messages.
+    fn payloads(self) -> Payloads<Self> {
+        Payloads { stream: self }
+    }
+
+    /// Decode the payloads of the channel and pattern messages with a codec,
+    /// dropping the other messages.
+    ///
+    /// Payloads which cannot be decoded are yielded as a [DecodeError].
+    fn decode<T>(self, codec: impl Codec<T> + 'static) -> Decoded<Self, T> {
+        Decoded {
+            stream: self,
+            codec: Arc::new(codec),
+        }
+    }
+
+    /// Deliver only the latest message of every channel when the consumer falls behind,
+    /// optionally delayed by the debounce and throttle windows of the channels.
+    ///
+    /// See [Conflated].
+    fn conflate(self, conflation: Conflation) -> Conflated<Self> {
+        Conflated::new(self, conflation)
+    }
+}
+
+impl<S> MessageStreamExt for S where S: Stream<Item = Message> + Unpin {}
+
+/// Which messages are kept by a [MessageFilter].
+#[derive(Debug, Clone)]
+enum Filter {
+    Messages,
+    Channel(String),
+    Pattern(String),
+    Control,
+}
+
+impl Filter {
+    /// Whether the message is kept.
+    fn accepts(&self, msg: &Message) -> bool {
+        match self {
+            Self::Messages => msg.channel().is_some(),
+            Self::Channel(name) => msg.channel() == Some(name.as_str()),
+            Self::Pattern(pattern) => msg
+                .channel()
+                .is_some_and(|channel| glob::matches(pattern, channel)),
+            Self::Control => msg.metadata().is_none(),
+        }
+    }
+}
+
+/// Stream of the messages kept by a filter, see [MessageStreamExt].
+#[derive(Debug)]
+pub struct MessageFilter<S> {
+    /// The filtered stream.
+    stream: S,
+    /// Which messages are kept.
+    filter: Filter,
+}
+
+impl<S> MessageFilter<S> {
+    fn new(stream: S, filter: Filter) -> Self {
+        Self { stream, filter }
+    }
+}
+
+impl<S> Stream for MessageFilter<S>
+where
+    S: Stream<Item = Message> + Unpin,
+{
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        loop {
+            let msg = match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if self.filter.accepts(&msg) {
+                return Poll::Ready(Some(msg));
+            }
+        }
+    }
+}
+
+/// Stream of the payloads of messages, see [MessageStreamExt::payloads].
+#[derive(Debug)]
+pub struct Payloads<S> {
+    /// The stream of messages.
+    stream: S,
+}
+
+impl<S> Stream for Payloads<S>
+where
+    S: Stream<Item = Message> + Unpin,
+{
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        loop {
+            let msg = match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match msg {
+                Message::Message { message, .. } | Message::PatternMessage { message, .. } => {
+                    return Poll::Ready(Some(message.into_bytes()))
+                }
+                Message::Binary { payload, .. } => return Poll::Ready(Some(payload)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Stream of the decoded payloads of messages, see [MessageStreamExt::decode].
+pub struct Decoded<S, T> {
+    /// The stream of messages.
+    stream: S,
+    /// Codec of the payloads.
+    codec: Arc<dyn Codec<T>>,
+}
+
+impl<S: Debug, T> Debug for Decoded<S, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Decoded")
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+impl<S, T> Stream for Decoded<S, T>
+where
+    S: Stream<Item = Message> + Unpin,
+{
+    type Item = Result<T, DecodeError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let msg = match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if let Some(res) = typed::decode(&*self.codec, &msg) {
+                return Poll::Ready(Some(res));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Text;
+    use tokio_stream::StreamExt;
+
+    /// A stream of a subscription, messages on three channels and the end of the stream.
+    fn messages() -> impl Stream<Item = Message> + Unpin {
+        tokio_stream::iter(vec![
+            Message::Subscription {
+                channel: "orders.eu".to_string(),
+                subscriptions: 1,
+            },
+            Message::test("orders.eu", "1"),
+            Message::test("prices", "x"),
+            Message::test("orders.us", "2"),
+            Message::Closed,
+        ])
+    }
+
+    /// The channels of the messages yielded by a stream.
+    async fn channels<S: Stream<Item = Message> + Unpin>(stream: S) -> Vec<String> {
+        stream
+            .map(|msg| msg.channel().unwrap_or_default().to_string())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn combinators() {
+        assert_eq!(
+            channels(messages().only_messages()).await,
+            ["orders.eu", "prices", "orders.us"]
+        );
+        assert_eq!(
+            channels(messages().for_channel("orders.eu")).await,
+            ["orders.eu"]
+        );
+        assert_eq!(
+            channels(messages().matching("orders.*")).await,
+            ["orders.eu", "orders.us"]
+        );
+        assert_eq!(
+            messages().control_events().collect::<Vec<_>>().await.len(),
+            2
+        );
+        assert_eq!(
+            messages().payloads().collect::<Vec<_>>().await,
+            [b"1".to_vec(), b"x".to_vec(), b"2".to_vec()]
+        );
+
+        let decoded = messages().decode::<u32>(Text).collect::<Vec<_>>().await;
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[0].as_ref().unwrap(), &1);
+        assert_eq!(decoded[1].as_ref().unwrap_err().channel, "prices");
+        assert_eq!(decoded[2].as_ref().unwrap(), &2);
+    }
+}
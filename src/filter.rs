@@ -0,0 +1,300 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio_stream::Stream;
+
+use crate::{glob, Message, RedisSub, Subscription};
+
+/// Separator of the levels of topics, unless set with `.with_topic_separator()`.
+pub(crate) const DEFAULT_SEPARATOR: char = '/';
+
+/// Level of a topic filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Level {
+    /// Matches exactly this level.
+    Literal(String),
+    /// `+` matches any single level.
+    Single,
+    /// `#` matches any amount of levels, including none, and is always last.
+    Multi,
+}
+
+/// A hierarchical topic filter, like `orders/+/created` or `orders/#`.
+///
+/// Topics are channel names of which the levels are separated by a separator, `/` by default.
+/// Like with MQTT, `+` matches any single level and `#` matches the level before it
+/// and any amount of levels after it.
+/// Wildcards must take up a whole level, and `#` must be the last level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicFilter {
+    /// The filter, as given.
+    filter: String,
+    /// Separator of the levels.
+    separator: char,
+    /// The levels of the filter.
+    levels: Vec<Level>,
+}
+
+impl TopicFilter {
+    /// Parse a topic filter with the separator of its levels.
+    ///
+    /// # Errors
+    /// Returns an error if the filter is empty, if a wildcard does not take up a whole level,
+    /// or if `#` is not the last level.
+    pub fn new(filter: &str, separator: char) -> crate::Result<Self> {
+        let invalid = || crate::Error::InvalidFilter(filter.to_string());
+        if filter.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut levels = Vec::new();
+        for level in filter.split(separator) {
+            if levels.last() == Some(&Level::Multi) {
+                return Err(invalid());
+            }
+
+            levels.push(match level {
+                "+" => Level::Single,
+                "#" => Level::Multi,
+                level if level.contains(['+', '#']) => return Err(invalid()),
+                level => Level::Literal(level.to_string()),
+            });
+        }
+
+        Ok(Self {
+            filter: filter.to_string(),
+            separator,
+            levels,
+        })
+    }
+
+    /// The filter, as given.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.filter
+    }
+
+    /// The separator of the levels.
+    #[must_use]
+    pub fn separator(&self) -> char {
+        self.separator
+    }
+
+    /// Whether a topic matches the filter.
+    #[must_use]
+    pub fn matches(&self, topic: &str) -> bool {
+        let mut levels = topic.split(self.separator);
+
+        for level in &self.levels {
+            match level {
+                Level::Multi => return true,
+                Level::Single => {
+                    if levels.next().is_none() {
+                        return false;
+                    }
+                }
+                Level::Literal(literal) => {
+                    if levels.next() != Some(literal.as_str()) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        levels.next().is_none()
+    }
+
+    /// The nearest pattern matching every topic matching the filter, see [glob].
+    ///
+    /// A `*` also matches separators, so the pattern can match more topics than the filter.
+    #[must_use]
+    pub fn pattern(&self) -> String {
+        let separator = glob::escape(&self.separator.to_string());
+        let mut pattern = String::new();
+
+        for (i, level) in self.levels.iter().enumerate() {
+            match level {
+                // `#` also matches the level before it, so the separator is optional.
+                Level::Multi => {
+                    pattern.push('*');
+                    break;
+                }
+                Level::Single => pattern.push('*'),
+                Level::Literal(literal) => pattern.push_str(&glob::escape(literal)),
+            }
+
+            if let Some(next) = self.levels.get(i + 1) {
+                if *next != Level::Multi {
+                    pattern.push_str(&separator);
+                }
+            }
+        }
+
+        pattern
+    }
+}
+
+impl RedisSub {
+    /// Subscribe to the topics matching a hierarchical topic filter, see [TopicFilter].
+    ///
+    /// The levels are separated by the separator set with `.with_topic_separator()`.
+    /// This subscribes to the nearest pattern of the filter,
+    /// and the returned handle only yields the messages of the topics matching the filter.
+    ///
+    /// # Errors
+    /// Returns an error if the filter is invalid,
+    /// or if an error happens on the underlying TCP stream.
+    pub async fn subscribe_filter(
+        self: &Arc<Self>,
+        filter: &str,
+    ) -> crate::Result<FilterSubscription> {
+        let filter = TopicFilter::new(filter, self.topic_separator)?;
+        let subscription = self.psubscription(filter.pattern()).await?;
+
+        Ok(FilterSubscription::new(subscription, filter))
+    }
+}
+
+/// Handle to a hierarchical topic filter subscription.
+///
+/// This is a stream of the messages of the topics matching the filter,
+/// and the [Message::Gap]s affecting it.
+/// Dropping the handle releases the subscription, like with [Subscription].
+#[derive(Debug)]
+pub struct FilterSubscription {
+    /// The subscription of the pattern of the filter.
+    subscription: Subscription,
+    /// The topic filter.
+    filter: TopicFilter,
+}
+
+impl FilterSubscription {
+    pub(crate) fn new(subscription: Subscription, filter: TopicFilter) -> Self {
+        Self {
+            subscription,
+            filter,
+        }
+    }
+
+    /// The topic filter of this handle.
+    #[must_use]
+    pub fn filter(&self) -> &TopicFilter {
+        &self.filter
+    }
+
+    /// Whether a message is on a topic matching the filter.
+    fn accepts(&self, msg: &Message) -> bool {
+        match msg {
+            Message::PatternMessage { channel, .. } | Message::Binary { channel, .. } => {
+                self.filter.matches(channel)
+            }
+            _ => true,
+        }
+    }
+
+    /// Receive the next message of a topic matching the filter.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            let msg = self.subscription.recv().await?;
+            if self.accepts(&msg) {
+                return Some(msg);
+            }
+        }
+    }
+}
+
+impl Stream for FilterSubscription {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        loop {
+            let msg = match Pin::new(&mut self.subscription).poll_next(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if self.accepts(&msg) {
+                return Poll::Ready(Some(msg));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+
+    #[test]
+    fn filter() {
+        let filter = TopicFilter::new("orders/+/created", '/').unwrap();
+        assert_eq!(filter.pattern(), "orders/*/created");
+        assert!(filter.matches("orders/eu/created"));
+        assert!(filter.matches("orders//created"));
+        assert!(!filter.matches("orders/eu/x/created"));
+        assert!(!filter.matches("orders/eu/created/x"));
+        assert!(glob::matches(&filter.pattern(), "orders/eu/x/created"));
+
+        let filter = TopicFilter::new("orders/#", '/').unwrap();
+        assert_eq!(filter.pattern(), "orders*");
+        assert!(filter.matches("orders"));
+        assert!(filter.matches("orders/eu/created"));
+        assert!(!filter.matches("ordersx"));
+        assert_eq!(TopicFilter::new("#", '/').unwrap().pattern(), "*");
+
+        let filter = TopicFilter::new("a*.+.[b]", '.').unwrap();
+        assert_eq!(filter.pattern(), "a\\*.*.\\[b\\]");
+        assert!(filter.matches("a*.x.[b]"));
+        assert!(!filter.matches("ab.x.[b]"));
+
+        for invalid in ["", "orders/+x", "orders/#/created", "orders#"] {
+            assert!(
+                TopicFilter::new(invalid, '/').is_err(),
+                "filter {:?} was valid",
+                invalid
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filter_subscription() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let (handle, _receiver) = RedisSub::new("127.0.0.1:6379")
+            .with_topic_separator('.')
+            .spawn()
+            .await
+            .expect("failed to connect to redis");
+        let mut subscription = handle
+            .subscribe_filter("filter.+.created")
+            .await
+            .expect("failed to subscribe to topic filter");
+        assert_eq!(subscription.filter().pattern(), "filter.*.created");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // The pattern also matches the first topic, but the filter does not.
+        for topic in ["filter.eu.x.created", "filter.eu.created"] {
+            connection
+                .publish::<&str, &str, u32>(topic, "hello")
+                .await
+                .expect("failed to send publish command to Redis");
+        }
+
+        let msg = tokio::time::timeout(Duration::from_secs(2), subscription.recv())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message");
+        match msg {
+            Message::PatternMessage { channel, .. } => assert_eq!(channel, "filter.eu.created"),
+            msg => panic!("message was not `PatternMessage`: {:?}", msg),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Glob-style pattern matching, as used by Redis for `PSUBSCRIBE` and `KEYS`.
+//!
+//! This is a port of `stringmatchlen` from the Redis source, so channels match
+//! locally exactly like they do on the server:
+//!
+//! - `?` matches any single byte,
+//! - `*` matches any amount of bytes,
+//! - `[abc]` matches one of the bytes, `[a-z]` a range and `[^a]` any byte but these,
+//! - `\` escapes the next byte, also inside brackets.
+//!
+//! Like on the server, a pattern of only `*` does not match the empty string.
+
+/// Nesting of `*` after which matching gives up, protecting against abusive patterns.
+const MAX_NESTING: usize = 1000;
+
+/// Whether the channel matches the pattern.
+#[must_use]
+pub fn matches(pattern: &str, channel: &str) -> bool {
+    string_match(pattern.as_bytes(), channel.as_bytes(), false)
+}
+
+/// Escape the special characters of a string, so it only matches itself as a pattern.
+#[must_use]
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether the string matches the pattern, optionally ignoring ASCII case.
+#[must_use]
+pub fn string_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let mut skip_longer_matches = false;
+    string_match_impl(pattern, string, nocase, &mut skip_longer_matches, 0)
+}
+
+/// The byte at a position, or zero past the end like the terminator of a C string.
+fn at(bytes: &[u8], i: usize) -> u8 {
+    bytes.get(i).copied().unwrap_or(0)
+}
+
+/// Whether two bytes are equal, optionally ignoring ASCII case.
+fn eq(a: u8, b: u8, nocase: bool) -> bool {
+    if nocase {
+        a.eq_ignore_ascii_case(&b)
+    } else {
+        a == b
+    }
+}
+
+fn string_match_impl(
+    pattern: &[u8],
+    string: &[u8],
+    nocase: bool,
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    let mut p = 0;
+    let mut s = 0;
+    while p < pattern.len() && s < string.len() {
+        match pattern[p] {
+            b'*' => {
+                while at(pattern, p + 1) == b'*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                while s < string.len() {
+                    if string_match_impl(
+                        &pattern[p + 1..],
+                        &string[s..],
+                        nocase,
+                        skip_longer_matches,
+                        nesting + 1,
+                    ) {
+                        return true;
+                    }
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                    s += 1;
+                }
+
+                // The rest of the pattern matches nowhere in the rest of the string,
+                // so earlier stars matching longer substrings cannot help either.
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => s += 1,
+            b'[' => {
+                p += 1;
+                let not = at(pattern, p) == b'^';
+                if not {
+                    p += 1;
+                }
+
+                let mut matched = false;
+                loop {
+                    if at(pattern, p) == b'\\' && pattern.len() - p >= 2 {
+                        p += 1;
+                        if pattern[p] == string[s] {
+                            matched = true;
+                        }
+                    } else if at(pattern, p) == b']' {
+                        break;
+                    } else if p == pattern.len() {
+                        // Unterminated brackets end with the pattern.
+                        p -= 1;
+                        break;
+                    } else if pattern.len() - p >= 3 && pattern[p + 1] == b'-' {
+                        let (mut start, mut end, mut c) = (pattern[p], pattern[p + 2], string[s]);
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+                        if nocase {
+                            start = start.to_ascii_lowercase();
+                            end = end.to_ascii_lowercase();
+                            c = c.to_ascii_lowercase();
+                        }
+                        p += 2;
+                        if c >= start && c <= end {
+                            matched = true;
+                        }
+                    } else if eq(pattern[p], string[s], nocase) {
+                        matched = true;
+                    }
+                    p += 1;
+                }
+
+                if not {
+                    matched = !matched;
+                }
+                if !matched {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                if c == b'\\' && pattern.len() - p >= 2 {
+                    p += 1;
+                }
+                if !eq(pattern[p], string[s], nocase) {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+
+        p += 1;
+        if s == string.len() {
+            while at(pattern, p) == b'*' {
+                p += 1;
+            }
+            break;
+        }
+    }
+
+    p == pattern.len() && s == string.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Escape every byte, so the pattern only matches the string itself.
+    fn escape_all(s: &str) -> String {
+        s.chars().flat_map(|c| ['\\', c]).collect()
+    }
+
+    #[test]
+    fn redis_behaviour() {
+        let cases = [
+            ("h?llo", "hello", true),
+            ("h?llo", "hllo", false),
+            ("h*llo", "hllo", true),
+            ("h*llo", "heeeello", true),
+            ("h[ae]llo", "hallo", true),
+            ("h[ae]llo", "hillo", false),
+            ("h[^e]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h[a-b]llo", "hcllo", false),
+            // Reversed ranges are swapped.
+            ("h[b-a]llo", "hallo", true),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("h[\\]]llo", "h]llo", true),
+            // A dash before the closing bracket makes a range to `]`, which is reversed here.
+            ("[a-]", "^", true),
+            ("[a-]", "b", false),
+            // Unterminated brackets end with the pattern.
+            ("[abc", "b", true),
+            ("a[", "a[", false),
+            // A trailing backslash matches itself.
+            ("a\\", "a\\", true),
+            ("*", "anything", true),
+            ("*", "", false),
+            ("a*", "a", true),
+            ("a**b", "ab", true),
+            ("*a*b", "xxaxxb", true),
+            ("*a*b", "xxbxxa", false),
+            ("news.*", "news.art.figurative", true),
+            ("news.*", "news", false),
+            ("", "", true),
+            ("", "a", false),
+        ];
+
+        for (pattern, string, expected) in cases {
+            assert_eq!(
+                matches(pattern, string),
+                expected,
+                "pattern {:?} with string {:?}",
+                pattern,
+                string
+            );
+        }
+    }
+
+    #[test]
+    fn nocase() {
+        assert!(string_match(b"H[A-C]LLO", b"hbllo", true));
+        assert!(string_match(b"h?LLO", b"Hello", true));
+        assert!(!string_match(b"h?LLO", b"Hello", false));
+    }
+
+    #[test]
+    fn abusive_pattern() {
+        // Matching gives up once the stars are nested too deep, even though this would match.
+        let pattern = "a*".repeat(2000);
+        let string = "a".repeat(2000) + "b";
+        assert!(!matches(&pattern, &string));
+    }
+
+    proptest! {
+        #[test]
+        fn star_matches_non_empty(s in ".+") {
+            prop_assert!(matches("*", &s));
+        }
+
+        #[test]
+        fn escaped_matches_itself(s in ".*", other in ".*") {
+            let pattern = escape_all(&s);
+            prop_assert!(matches(&pattern, &s));
+            prop_assert_eq!(matches(&pattern, &other), s == other);
+
+            let pattern = escape(&s);
+            prop_assert!(matches(&pattern, &s));
+            prop_assert_eq!(matches(&pattern, &other), s == other);
+        }
+
+        #[test]
+        fn questions_match_length(s in "[a-z]{1,16}", n in 1usize..16) {
+            prop_assert_eq!(matches(&"?".repeat(n), &s), s.len() == n);
+        }
+
+        #[test]
+        fn contains(s in "[a-z]{0,8}", prefix in "[a-z]{0,8}", suffix in "[a-z]{1,8}") {
+            let pattern = format!("*{}*", s);
+            let string = format!("{}{}{}", prefix, s, suffix);
+            prop_assert!(matches(&pattern, &string));
+        }
+
+        #[test]
+        fn class_matches_byte(c in proptest::char::range('a', 'z'), start in proptest::char::range('a', 'z'), end in proptest::char::range('a', 'z')) {
+            let (low, high) = if start <= end { (start, end) } else { (end, start) };
+            let in_range = low <= c && c <= high;
+            prop_assert_eq!(matches(&format!("[{}-{}]", start, end), &c.to_string()), in_range);
+            prop_assert_eq!(matches(&format!("[^{}-{}]", start, end), &c.to_string()), !in_range);
+        }
+    }
+}
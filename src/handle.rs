@@ -0,0 +1,315 @@
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio::sync::{mpsc, oneshot, Mutex, Notify};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{Message, RedisSub};
+
+/// Amount of messages buffered for every receiver before the background task waits.
+const RECEIVER_CAPACITY: usize = 1024;
+
+/// State shared between the handles and the background task.
+#[derive(Debug)]
+struct Shared {
+    /// The subscription object driven by the background task.
+    sub: Arc<RedisSub>,
+    /// Senders for all registered receivers.
+    receivers: Mutex<Vec<mpsc::Sender<Message>>>,
+    /// Notified when the last handle is dropped.
+    shutdown: Notify,
+}
+
+/// Notifies the background task once all handles are dropped.
+#[derive(Debug)]
+struct HandleGuard {
+    shared: Arc<Shared>,
+}
+
+impl Drop for HandleGuard {
+    fn drop(&mut self) {
+        self.shared.shutdown.notify_one();
+    }
+}
+
+/// Cloneable handle to a [RedisSub] which is driven by a background task.
+///
+/// Created by [RedisSub::spawn], the task stops once all handles are dropped.
+/// The handle derefs to the shared [RedisSub], so all its methods can be called on the handle,
+/// and it can be cloned into an `Arc<RedisSub>`, for example to create a [Router].
+/// Do not call `.listen()` on it, the background task is already listening.
+///
+/// [Router]: crate::Router
+#[derive(Debug, Clone)]
+pub struct SubscriberHandle {
+    guard: Arc<HandleGuard>,
+}
+
+impl SubscriberHandle {
+    /// Create an additional receiver for all messages from now on.
+    pub async fn receiver(&self) -> MessageReceiver {
+        register(&self.guard.shared).await
+    }
+}
+
+impl Deref for SubscriberHandle {
+    type Target = Arc<RedisSub>;
+
+    fn deref(&self) -> &Arc<RedisSub> {
+        &self.guard.shared.sub
+    }
+}
+
+/// Receives the messages from a spawned [RedisSub].
+///
+/// Every receiver gets its own copy of every message.
+/// The background task waits when a receiver falls behind, so keep all receivers polled.
+/// It still stops once all handles are dropped, even while waiting for a receiver.
+#[derive(Debug)]
+pub struct MessageReceiver {
+    rx: mpsc::Receiver<Message>,
+}
+
+impl MessageReceiver {
+    /// Receive the next message.
+    ///
+    /// Returns `None` once the background task has stopped.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.rx.recv().await
+    }
+}
+
+impl Stream for MessageReceiver {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// Register a new receiver with the background task.
+async fn register(shared: &Shared) -> MessageReceiver {
+    let (tx, rx) = mpsc::channel(RECEIVER_CAPACITY);
+    shared.receivers.lock().await.push(tx);
+
+    MessageReceiver { rx }
+}
+
+/// Send a message to all registered receivers, removing the closed ones.
+async fn dispatch(shared: &Shared, msg: Message) {
+    let receivers = shared.receivers.lock().await.clone();
+    let mut closed = false;
+
+    for tx in &receivers {
+        closed |= tx.send(msg.clone()).await.is_err();
+    }
+
+    if closed {
+        shared.receivers.lock().await.retain(|tx| !tx.is_closed());
+    }
+}
+
+impl RedisSub {
+    /// Move the subscription object into a background task which listens for messages.
+    ///
+    /// Returns a cloneable handle to manage subscriptions and a receiver for the messages.
+    /// The task and its connection stop once all handles are dropped.
+    ///
+    /// # Errors
+    /// Returns an error if the first connection attempt fails.
+    pub async fn spawn(self) -> crate::Result<(SubscriberHandle, MessageReceiver)> {
+        let shared = Arc::new(Shared {
+            sub: Arc::new(self),
+            receivers: Mutex::new(Vec::new()),
+            shutdown: Notify::new(),
+        });
+        let receiver = register(&shared).await;
+        let (connected_tx, connected_rx) = oneshot::channel();
+
+        let task_shared = shared.clone();
+        tokio::spawn(async move {
+            let shared = task_shared;
+            let sub = shared.sub.clone();
+
+            let mut stream = match sub.listen().await {
+                Ok(stream) => {
+                    let _ = connected_tx.send(Ok(()));
+                    stream
+                }
+                Err(e) => {
+                    let _ = connected_tx.send(Err(e));
+                    return;
+                }
+            };
+
+            loop {
+                let msg = tokio::select! {
+                    _ = shared.shutdown.notified() => break,
+                    msg = stream.next() => match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                };
+
+                // A receiver which is not polled must not keep the task from stopping.
+                tokio::select! {
+                    _ = shared.shutdown.notified() => break,
+                    _ = dispatch(&shared, msg) => {}
+                }
+            }
+
+            // Subscription handles keep the subscription object alive, so close its connection.
+            drop(stream);
+            *sub.writer.lock().await = None;
+            debug!("all handles dropped, stopped background task");
+        });
+
+        connected_rx
+            .await
+            .expect("background task stopped before connecting")?;
+
+        let handle = SubscriberHandle {
+            guard: Arc::new(HandleGuard { shared }),
+        };
+        Ok((handle, receiver))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+
+    async fn next(receiver: &mut MessageReceiver) -> Message {
+        tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message")
+    }
+
+    #[tokio::test]
+    async fn test_stops_with_full_receiver() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Reply to `CLIENT ID`, then flood the connection with messages.
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 64];
+                if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                    continue;
+                }
+                let _ = socket.write_all(b":7\r\n").await;
+                let flood = b"*3\r\n$7\r\nmessage\r\n$5\r\nflood\r\n$1\r\n1\r\n"
+                    .repeat(2 * RECEIVER_CAPACITY);
+                let _ = socket.write_all(&flood).await;
+                let _ = socket.read(&mut buf).await;
+            }
+        });
+
+        let (handle, receiver) = RedisSub::new(&addr)
+            .spawn()
+            .await
+            .expect("failed to connect to listener");
+        let shared = Arc::downgrade(&handle.guard.shared);
+
+        // Let the receiver fill up without polling it.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        drop(handle);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            shared.strong_count(),
+            0,
+            "background task did not stop with a full receiver"
+        );
+        drop(receiver);
+    }
+
+    #[tokio::test]
+    async fn test_closes_connection_when_stopped() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Reply to `CLIENT ID`, then wait for the connection to be closed.
+        let (closed_tx, closed_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 64];
+                if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                    continue;
+                }
+                let _ = socket.write_all(b":7\r\n").await;
+                while socket.read(&mut buf).await.unwrap_or(0) > 0 {}
+                let _ = closed_tx.send(());
+                break;
+            }
+        });
+
+        let (handle, mut receiver) = RedisSub::new(&addr)
+            .spawn()
+            .await
+            .expect("failed to connect to listener");
+        assert!(next(&mut receiver).await.is_connected());
+        let subscription = handle
+            .subscription("kept".to_string())
+            .await
+            .expect("failed to subscribe");
+
+        // The subscription handle outlives the handles, but not the connection.
+        drop(handle);
+        tokio::time::timeout(Duration::from_secs(2), closed_rx)
+            .await
+            .expect("connection was not closed after dropping all handles")
+            .expect("listener stopped");
+        drop((subscription, receiver));
+    }
+
+    #[tokio::test]
+    async fn test_spawned_receivers() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let (handle, mut first) = RedisSub::new("127.0.0.1:6379")
+            .spawn()
+            .await
+            .expect("failed to connect to redis");
+        assert!(next(&mut first).await.is_connected());
+        let mut second = handle.receiver().await;
+
+        handle
+            .clone()
+            .subscribe("spawned".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        assert!(next(&mut first).await.is_subscription());
+        assert!(next(&mut second).await.is_subscription());
+
+        connection
+            .publish::<&str, &str, u32>("spawned", "hello")
+            .await
+            .expect("failed to send publish command to Redis");
+        assert!(next(&mut first).await.is_message());
+        assert!(next(&mut second).await.is_message());
+
+        drop(handle);
+        let end = tokio::time::timeout(Duration::from_secs(2), first.recv())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded");
+        assert!(end.is_none(), "receiver did not end after dropping handles");
+    }
+}
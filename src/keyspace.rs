@@ -0,0 +1,525 @@
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio_stream::Stream;
+
+use crate::resp::Response;
+use crate::{glob, Connection, Message, ParserError, RedisSub, Subscription};
+
+/// Event classes enabled by the `A` flag of `notify-keyspace-events`.
+const ALL_CLASSES: &str = "g$lshzxetd";
+
+/// The operation which triggered a keyspace notification.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KeyEvent {
+    /// The key was deleted with `DEL` or `UNLINK`.
+    Del,
+    /// A string value was set, like with `SET`.
+    Set,
+    /// An expiration time was set on the key.
+    Expire,
+    /// The key expired.
+    Expired,
+    /// The key was evicted because of the `maxmemory` policy.
+    Evicted,
+    /// The expiration time of the key was removed.
+    Persist,
+    /// The key was renamed, this is the old name.
+    RenameFrom,
+    /// The key was renamed, this is the new name.
+    RenameTo,
+    /// A new key was added.
+    New,
+    /// Any other event, by its name.
+    Other(String),
+}
+
+impl KeyEvent {
+    /// Parse the name of an event, as sent by the server.
+    #[must_use]
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "del" => Self::Del,
+            "set" => Self::Set,
+            "expire" => Self::Expire,
+            "expired" => Self::Expired,
+            "evicted" => Self::Evicted,
+            "persist" => Self::Persist,
+            "rename_from" => Self::RenameFrom,
+            "rename_to" => Self::RenameTo,
+            "new" => Self::New,
+            name => Self::Other(name.to_string()),
+        }
+    }
+
+    /// The name of the event, as sent by the server.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Del => "del",
+            Self::Set => "set",
+            Self::Expire => "expire",
+            Self::Expired => "expired",
+            Self::Evicted => "evicted",
+            Self::Persist => "persist",
+            Self::RenameFrom => "rename_from",
+            Self::RenameTo => "rename_to",
+            Self::New => "new",
+            Self::Other(name) => name,
+        }
+    }
+
+    /// The `notify-keyspace-events` flag enabling this event, `None` if it is unknown.
+    fn class(&self) -> Option<char> {
+        match self {
+            Self::Del | Self::Expire | Self::Persist | Self::RenameFrom | Self::RenameTo => {
+                Some('g')
+            }
+            Self::Set => Some('$'),
+            Self::Expired => Some('x'),
+            Self::Evicted => Some('e'),
+            Self::New => Some('n'),
+            Self::Other(_) => None,
+        }
+    }
+}
+
+/// A keyspace notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyspaceEvent {
+    /// The database of the key.
+    pub db: u32,
+    /// The key which was changed.
+    pub key: String,
+    /// The operation which changed the key.
+    pub event: KeyEvent,
+}
+
+impl KeyspaceEvent {
+    /// Parse a message on a `__keyspace@<db>__:<key>` or `__keyevent@<db>__:<event>` channel.
+    ///
+    /// # Errors
+    /// Returns an error if the channel is not a keyspace or keyevent channel.
+    pub fn from_message(channel: &str, message: &str) -> crate::Result<Self> {
+        let (keyspace, rest) = match channel.strip_prefix("__keyspace@") {
+            Some(rest) => (true, rest),
+            None => (
+                false,
+                channel
+                    .strip_prefix("__keyevent@")
+                    .ok_or(ParserError::InvalidChannel)?,
+            ),
+        };
+        let (db, name) = rest.split_once("__:").ok_or(ParserError::InvalidChannel)?;
+
+        // Keyspace channels name the key, keyevent channels name the event.
+        let (key, event) = if keyspace {
+            (name, message)
+        } else {
+            (message, name)
+        };
+        Ok(Self {
+            db: db.parse().map_err(|_| ParserError::InvalidChannel)?,
+            key: key.to_string(),
+            event: KeyEvent::from_name(event),
+        })
+    }
+}
+
+/// An item of a [KeyspaceSubscription].
+#[derive(Debug, Clone)]
+pub enum KeyspaceItem {
+    /// A notification of a subscribed event.
+    Event(KeyspaceEvent),
+    /// Notifications were missed, as reported by a [Message::Gap] or [Message::Lagged].
+    ///
+    /// Keys tracked from the notifications should be read again.
+    Missed(Message),
+}
+
+/// The pattern of the keyspace channels of the keys matching a pattern.
+fn pattern(db: u32, key_pattern: &str) -> String {
+    format!("__keyspace@{}__:{}", db, key_pattern)
+}
+
+/// The channel of the keyevent notifications of an event.
+fn event_channel(db: u32, event: &KeyEvent) -> String {
+    format!("__keyevent@{}__:{}", db, event.name())
+}
+
+/// The `notify-keyspace-events` flags needed for the events, all events if empty.
+///
+/// Keyspace notifications are needed for all events, keyevent notifications for specific events.
+fn required_flags(events: &[KeyEvent]) -> String {
+    let mut flags = String::from(if events.is_empty() { "K" } else { "E" });
+    if events.is_empty() || events.iter().any(|event| event.class().is_none()) {
+        flags.push('A');
+    }
+
+    for class in events.iter().filter_map(KeyEvent::class) {
+        if !flags.contains(class) {
+            flags.push(class);
+        }
+    }
+    flags
+}
+
+/// Whether the configured flags enable the required flags.
+fn flags_enabled(configured: &str, required: &str) -> bool {
+    required.chars().all(|flag| {
+        configured.contains(flag) || (configured.contains('A') && ALL_CLASSES.contains(flag))
+    })
+}
+
+impl Connection {
+    /// Enable the keyspace notifications of the events on the server, all events if empty.
+    ///
+    /// This enables the notifications used by `.subscribe_keyspace()` for the same events.
+    /// The configured `notify-keyspace-events` are only extended, and not changed
+    /// when they already include the events.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream,
+    /// or if the server replied with an error, for example when `CONFIG` is disabled.
+    pub async fn enable_keyspace_events(&mut self, events: &[KeyEvent]) -> crate::Result<()> {
+        let configured = match self
+            .command(["CONFIG", "GET", "notify-keyspace-events"])
+            .await?
+        {
+            Response::Array(values) => match values.get(1) {
+                Some(Response::Bulk(value)) => String::from_utf8_lossy(value).into_owned(),
+                _ => String::new(),
+            },
+            _ => return Err(ParserError::MalformedResponse.into()),
+        };
+
+        let required = required_flags(events);
+        if flags_enabled(&configured, &required) {
+            return Ok(());
+        }
+
+        let mut flags = configured;
+        for flag in required.chars() {
+            if !flags.contains(flag) {
+                flags.push(flag);
+            }
+        }
+        debug!("setting notify-keyspace-events to {}", flags);
+        self.command(["CONFIG", "SET", "notify-keyspace-events", &flags])
+            .await?;
+        Ok(())
+    }
+}
+
+impl RedisSub {
+    /// Subscribe to the keyspace notifications of the keys matching a pattern.
+    ///
+    /// Returns a handle which yields the notifications of the given events, all events if empty.
+    /// Given events are subscribed to on their `__keyevent@<db>__:<event>` channels,
+    /// so the server only sends those events, and the keys are matched by the handle.
+    /// These events are sent for every key of the database,
+    /// so with a narrow key pattern on a busy database most of them are received only to be dropped.
+    /// Subscribe without events in that case, which only receives the events of the matching keys,
+    /// and filter the events with [KeyspaceEvent::event].
+    /// Without events, the `__keyspace@<db>__:<pattern>` channels are subscribed to.
+    /// The server only sends the enabled notifications,
+    /// see `.enable_keyspace_events()` to enable them.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn subscribe_keyspace(
+        self: &Arc<Self>,
+        db: u32,
+        key_pattern: &str,
+        events: &[KeyEvent],
+    ) -> crate::Result<KeyspaceSubscription> {
+        let mut subscriptions = Vec::new();
+        if events.is_empty() {
+            subscriptions.push(self.psubscription(pattern(db, key_pattern)).await?);
+        }
+        for event in events {
+            subscriptions.push(self.subscription(event_channel(db, event)).await?);
+        }
+
+        Ok(KeyspaceSubscription::new(
+            subscriptions,
+            key_pattern.to_string(),
+            events.to_vec(),
+        ))
+    }
+
+    /// Enable the keyspace notifications of the events on the server, all events if empty.
+    ///
+    /// This uses a separate connection, use [Connection::enable_keyspace_events]
+    /// in an initializer to enable them on every connection instead.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream,
+    /// or if the server replied with an error, for example when `CONFIG` is disabled.
+    pub async fn enable_keyspace_events(&self, events: &[KeyEvent]) -> crate::Result<()> {
+        let mut conn = self.side_connection().await?;
+        conn.enable_keyspace_events(events).await
+    }
+}
+
+/// Handle to a keyspace notification subscription.
+///
+/// This is a stream of the [KeyspaceEvent]s of the subscribed keys and events,
+/// and of the notices of missed notifications.
+/// Dropping the handle releases the subscriptions, like with [Subscription].
+#[derive(Debug)]
+pub struct KeyspaceSubscription {
+    /// The subscriptions of the keyspace pattern or of the keyevent channels.
+    subscriptions: Vec<Subscription>,
+    /// The pattern of the keys to keep.
+    key_pattern: String,
+    /// The events to keep, all events if empty.
+    events: Vec<KeyEvent>,
+    /// The subscription polled first, so a busy one does not starve the others.
+    next: usize,
+}
+
+impl KeyspaceSubscription {
+    pub(crate) fn new(
+        subscriptions: Vec<Subscription>,
+        key_pattern: String,
+        events: Vec<KeyEvent>,
+    ) -> Self {
+        Self {
+            subscriptions,
+            key_pattern,
+            events,
+            next: 0,
+        }
+    }
+
+    /// Parse a message, returns `None` if it is not a subscribed event or a missed notice.
+    fn parse(&self, msg: Message) -> Option<KeyspaceItem> {
+        let event = match msg {
+            Message::Message {
+                channel, message, ..
+            }
+            | Message::PatternMessage {
+                channel, message, ..
+            } => match KeyspaceEvent::from_message(&channel, &message) {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("invalid keyspace notification on {}: {:?}", channel, e);
+                    return None;
+                }
+            },
+            Message::Gap { .. } | Message::Lagged(_) => return Some(KeyspaceItem::Missed(msg)),
+            _ => return None,
+        };
+
+        let subscribed = self.events.is_empty() || self.events.contains(&event.event);
+        if subscribed && glob::matches(&self.key_pattern, &event.key) {
+            Some(KeyspaceItem::Event(event))
+        } else {
+            None
+        }
+    }
+
+    /// Receive the next keyspace notification, or notice of missed notifications.
+    pub async fn recv(&mut self) -> Option<KeyspaceItem> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for KeyspaceSubscription {
+    type Item = KeyspaceItem;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<KeyspaceItem>> {
+        let this = &mut *self;
+
+        // Take the messages of every subscription, until one is an item to yield,
+        // starting after the subscription of the last event.
+        let len = this.subscriptions.len();
+        let mut ended = 0;
+        for i in (0..len).map(|offset| (this.next + offset) % len) {
+            loop {
+                let msg = match Pin::new(&mut this.subscriptions[i]).poll_next(cx) {
+                    Poll::Ready(Some(msg)) => msg,
+                    Poll::Ready(None) => {
+                        ended += 1;
+                        break;
+                    }
+                    Poll::Pending => break,
+                };
+
+                if let Some(item) = this.parse(msg) {
+                    this.next = (i + 1) % len;
+                    return Poll::Ready(Some(item));
+                }
+            }
+        }
+
+        if ended == len {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subscription::Target;
+    use crate::Metadata;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+
+    #[test]
+    fn keyspace_event() {
+        assert_eq!(
+            KeyspaceEvent::from_message("__keyspace@3__:user:1", "expired").unwrap(),
+            KeyspaceEvent {
+                db: 3,
+                key: "user:1".to_string(),
+                event: KeyEvent::Expired,
+            }
+        );
+        assert_eq!(
+            KeyspaceEvent::from_message("__keyspace@0__:a__:b", "hset")
+                .unwrap()
+                .event,
+            KeyEvent::Other("hset".to_string())
+        );
+        assert_eq!(
+            KeyspaceEvent::from_message("__keyevent@0__:set", "key").unwrap(),
+            KeyspaceEvent {
+                db: 0,
+                key: "key".to_string(),
+                event: KeyEvent::Set,
+            }
+        );
+        assert!(KeyspaceEvent::from_message("channel", "set").is_err());
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(required_flags(&[]), "KA");
+        assert_eq!(required_flags(&[KeyEvent::Set, KeyEvent::Expired]), "E$x");
+        assert_eq!(required_flags(&[KeyEvent::Del, KeyEvent::Expire]), "Eg");
+
+        assert!(flags_enabled("AK", "KA"));
+        assert!(flags_enabled("AE", "E$x"));
+        assert!(flags_enabled("xE$K", "E$x"));
+        assert!(!flags_enabled("Kx", "E$x"));
+        assert!(!flags_enabled("AE", "En"));
+    }
+
+    #[tokio::test]
+    async fn reports_missed_events() {
+        let sub = Arc::new(RedisSub::new("127.0.0.1:6379").with_subscription_capacity(1));
+        let pattern = pattern(0, "keyspace:*");
+        let subscription = Subscription::new(sub.clone(), Target::Pattern(pattern.clone()));
+        let mut events =
+            KeyspaceSubscription::new(vec![subscription], "keyspace:*".to_string(), Vec::new());
+
+        // Only the first notification fits in the handle.
+        for key in ["keyspace:1", "keyspace:2", "keyspace:3"] {
+            sub.routes.dispatch(&Message::PatternMessage {
+                pattern: pattern.clone(),
+                channel: format!("__keyspace@0__:{}", key),
+                message: "set".to_string(),
+                metadata: Metadata::test(),
+            });
+        }
+
+        match events.recv().await {
+            Some(KeyspaceItem::Event(event)) => assert_eq!(event.key, "keyspace:1"),
+            item => panic!("item was not an event: {:?}", item),
+        }
+        let missed = tokio::time::timeout(Duration::from_secs(2), events.recv())
+            .await
+            .expect("missed notifications were not reported");
+        assert!(matches!(
+            missed,
+            Some(KeyspaceItem::Missed(Message::Lagged(2)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_keyspace_subscription() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let (handle, mut receiver) = RedisSub::new("127.0.0.1:6379")
+            .spawn()
+            .await
+            .expect("failed to connect to redis");
+        assert!(receiver.recv().await.is_some_and(|msg| msg.is_connected()));
+
+        // Restore the configuration of the server afterwards, it is shared with other tests.
+        let configured: Vec<String> = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("notify-keyspace-events")
+            .query_async(&mut connection)
+            .await
+            .expect("failed to get config");
+
+        handle
+            .enable_keyspace_events(&[KeyEvent::Set, KeyEvent::Expired])
+            .await
+            .expect("failed to enable keyspace events");
+        let flags: Vec<String> = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("notify-keyspace-events")
+            .query_async(&mut connection)
+            .await
+            .expect("failed to get config");
+        redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg(&configured[1])
+            .query_async::<_, ()>(&mut connection)
+            .await
+            .expect("failed to restore config");
+        assert!(
+            flags_enabled(&flags[1], "E$x"),
+            "keyspace events were not enabled: {:?}",
+            flags
+        );
+
+        let mut events = handle
+            .subscribe_keyspace(0, "keyspace:*", &[KeyEvent::Set])
+            .await
+            .expect("failed to subscribe to keyspace notifications");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Only the subscribed events of the matching keys are yielded.
+        for (event, key) in [
+            ("del", "keyspace:1"),
+            ("set", "other:1"),
+            ("set", "keyspace:1"),
+        ] {
+            connection
+                .publish::<String, &str, u32>(event_channel(0, &KeyEvent::from_name(event)), key)
+                .await
+                .expect("failed to send publish command to Redis");
+        }
+        let item = tokio::time::timeout(Duration::from_secs(2), events.recv())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded");
+        let event = match item {
+            Some(KeyspaceItem::Event(event)) => event,
+            item => panic!("item was not an event: {:?}", item),
+        };
+        assert_eq!(
+            event,
+            KeyspaceEvent {
+                db: 0,
+                key: "keyspace:1".to_string(),
+                event: KeyEvent::Set,
+            }
+        );
+    }
+}
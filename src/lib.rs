@@ -1,13 +1,52 @@
+mod broadcast;
+mod cache;
+pub mod codec;
 mod command;
+mod conflate;
+mod connection;
+mod entry;
 mod error;
+mod events;
+mod ext;
+mod filter;
+pub mod glob;
+mod handle;
+mod keyspace;
 mod message;
-mod parser;
+mod namespace;
+mod pairing;
 mod redis_sub;
+pub mod resp;
+mod router;
+mod stream_sub;
+mod subscription;
+pub mod topic;
+mod tracking;
+mod typed;
 
 #[macro_use]
 extern crate tracing;
 
+pub use crate::broadcast::BroadcastReceiver;
+pub use crate::cache::{CacheEpoch, InvalidatingCache};
 use crate::command::Command;
+pub use crate::conflate::{Conflated, Conflation};
+pub use crate::connection::{BoxFuture, Connection};
+pub use crate::entry::{StreamEntry, StreamId};
 pub use crate::error::*;
-pub use crate::message::Message;
-pub use redis_sub::RedisSub;
+pub use crate::events::ConnectionListener;
+pub use crate::ext::{Decoded, MessageFilter, MessageStreamExt, Payloads};
+pub use crate::filter::{FilterSubscription, TopicFilter};
+pub use crate::handle::{MessageReceiver, SubscriberHandle};
+pub use crate::keyspace::{KeyEvent, KeyspaceEvent, KeyspaceItem, KeyspaceSubscription};
+pub use crate::message::{LimitKind, Message, Metadata, ParserError};
+pub use crate::resp::Limits;
+pub use redis_sub::{OverflowPolicy, RedisSub};
+#[cfg(feature = "derive")]
+pub use redis_subscribe_derive::Topic;
+pub use router::{HandlerError, HandlerResult, Router};
+pub use stream_sub::StreamSub;
+pub use subscription::Subscription;
+pub use topic::{Topic, TopicSubscription};
+pub use tracking::{Invalidation, INVALIDATE_CHANNEL};
+pub use typed::{DecodeError, TypedChannel, TypedError, TypedSubscription};
@@ -1,9 +1,14 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Instant, SystemTime};
+
 use thiserror::Error;
 
-use super::parser;
-use crate::Error;
+use crate::resp::Response;
+use crate::tracking::INVALIDATE_CHANNEL;
+use crate::{Error, Invalidation, StreamEntry, StreamId};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Message {
     Subscription {
         channel: String,
@@ -16,6 +21,7 @@ pub enum Message {
     Message {
         channel: String,
         message: String,
+        metadata: Metadata,
     },
 
     PatternSubscription {
@@ -30,13 +36,91 @@ pub enum Message {
         pattern: String,
         channel: String,
         message: String,
+        metadata: Metadata,
+    },
+    /// A channel or pattern message with a payload which is not valid UTF-8.
+    ///
+    /// These messages used to be dropped as a parse error,
+    /// use [Message::payload] to handle the payloads of all messages.
+    Binary {
+        pattern: Option<String>,
+        channel: String,
+        payload: Vec<u8>,
+        metadata: Metadata,
+    },
+    /// An entry read from a stream by a [StreamSub].
+    ///
+    /// [StreamSub]: crate::StreamSub
+    Entry {
+        stream: String,
+        entry: StreamEntry,
+        metadata: Metadata,
+    },
+    /// Keys invalidated by client-side caching, received on the invalidation channel.
+    Invalidation {
+        invalidation: Invalidation,
+        metadata: Metadata,
+    },
+    Connected {
+        client_id: Option<i64>,
+    },
+    /// Messages of these channels and patterns may have been missed during the interval,
+    /// yielded after they are subscribed to again on a new connection.
+    Gap {
+        channels: Vec<String>,
+        patterns: Vec<String>,
+        since: SystemTime,
+        until: SystemTime,
     },
-    Connected,
     Disconnected(Error),
     Error(Error),
+    Lagged(u64),
+    Closed,
+    Fatal(Error),
+}
+
+/// Details on how and when a message was received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// When the message was read from the connection.
+    pub received: Instant,
+    /// Wall-clock time at which the message was read from the connection.
+    pub timestamp: SystemTime,
+    /// The connection the message was received on, incremented on every reconnect.
+    pub generation: u64,
+    /// Position of the message on its connection, starting at zero.
+    pub sequence: u64,
+    /// ID of the stream entry, for entries and messages of channels paired with a stream.
+    pub stream_id: Option<StreamId>,
+}
+
+#[cfg(test)]
+impl Metadata {
+    /// Metadata of the first message of the first connection, received now.
+    pub(crate) fn test() -> Self {
+        Self {
+            received: Instant::now(),
+            timestamp: SystemTime::now(),
+            generation: 1,
+            sequence: 0,
+            stream_id: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Message {
+    /// A channel message with a payload.
+    pub(crate) fn test(channel: &str, payload: &str) -> Self {
+        Self::Message {
+            channel: channel.to_string(),
+            message: payload.to_string(),
+            metadata: Metadata::test(),
+        }
+    }
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ParserError {
     #[error("The response has an invalid format.")]
     MalformedResponse,
@@ -46,23 +130,54 @@ pub enum ParserError {
     InvalidSubscriberCount,
     #[error("The provided pattern is invalid.")]
     InvalidPattern,
+    #[error("The provided stream ID is invalid.")]
+    InvalidStreamId,
+    #[error("The incoming data exceeded the {0} limit.")]
+    LimitExceeded(LimitKind),
+}
+
+/// The limit which was exceeded by incoming data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// A bulk string was longer than the maximum bulk length.
+    BulkLength,
+    /// An array had more elements than the maximum array length.
+    ArrayLength,
+    /// Arrays were nested deeper than the maximum array depth.
+    ArrayDepth,
+    /// A single frame did not fit in the maximum amount of buffered bytes.
+    BufferedBytes,
+}
+
+impl Display for LimitKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::BulkLength => "bulk length",
+            Self::ArrayLength => "array length",
+            Self::ArrayDepth => "array depth",
+            Self::BufferedBytes => "buffered bytes",
+        })
+    }
 }
 
 impl Message {
     /// Parse the response to a message.
     ///
+    /// The metadata is only kept if the response is a message.
+    ///
     /// # Errors
     /// Returns an error if the response has unexpected types.
-    pub fn from_response(res: parser::Response) -> crate::Result<Self> {
+    pub fn from_response(res: Response, metadata: Metadata) -> crate::Result<Self> {
         // Make sure the response is a array.
         let arr = match res {
-            parser::Response::Array(arr) => Ok(arr),
-            _ => Err(ParserError::MalformedResponse),
+            Response::Array(arr) => Ok(arr),
+            Response::Error(e) => Err(Error::ServerError(e)),
+            _ => Err(ParserError::MalformedResponse.into()),
         }?;
 
         // Get the first element of the array.
-        let channel = match arr.get(0) {
-            Some(parser::Response::Bulk(channel)) => Ok(channel.as_str()),
+        let channel = match arr.first() {
+            Some(Response::Bulk(channel)) => Ok(std::str::from_utf8(channel)?),
             _ => Err(ParserError::MalformedResponse),
         }?;
 
@@ -70,8 +185,8 @@ impl Message {
         match channel.to_lowercase().as_str() {
             "subscribe" => Self::from_subscribe(&arr),
             "unsubscribe" => Self::from_unsubscribe(&arr),
-            "message" => Self::from_message(&arr),
-            "pmessage" => Self::from_pmessage(&arr),
+            "message" => Self::from_message(&arr, metadata),
+            "pmessage" => Self::from_pmessage(&arr, metadata),
             "psubscribe" => Self::from_psubscribe(&arr),
             "punsubscribe" => Self::from_punsubscribe(&arr),
             _ => Err(Error::ParserError(ParserError::MalformedResponse)),
@@ -79,14 +194,14 @@ impl Message {
     }
 
     /// parse the subscription message.
-    fn from_subscribe(res: &[parser::Response]) -> crate::Result<Self> {
+    fn from_subscribe(res: &[Response]) -> crate::Result<Self> {
         let channel = match res.get(1) {
-            Some(parser::Response::Bulk(channel)) => Ok((*channel).clone()),
-            _ => Err(ParserError::InvalidChannel),
+            Some(Response::Bulk(channel)) => bulk_to_string(channel),
+            _ => Err(ParserError::InvalidChannel.into()),
         }?;
 
         let subscriptions = match res.get(2) {
-            Some(parser::Response::Integer(subscriptions)) => Ok(*subscriptions),
+            Some(Response::Integer(subscriptions)) => Ok(*subscriptions),
             _ => Err(ParserError::InvalidSubscriberCount),
         }?;
 
@@ -96,14 +211,14 @@ impl Message {
         })
     }
 
-    fn from_psubscribe(res: &[parser::Response]) -> crate::Result<Self> {
+    fn from_psubscribe(res: &[Response]) -> crate::Result<Self> {
         let channel = match res.get(1) {
-            Some(parser::Response::Bulk(channel)) => Ok((*channel).clone()),
-            _ => Err(ParserError::InvalidChannel),
+            Some(Response::Bulk(channel)) => bulk_to_string(channel),
+            _ => Err(ParserError::InvalidChannel.into()),
         }?;
 
         let subscriptions = match res.get(2) {
-            Some(parser::Response::Integer(subscriptions)) => Ok(*subscriptions),
+            Some(Response::Integer(subscriptions)) => Ok(*subscriptions),
             _ => Err(ParserError::InvalidSubscriberCount),
         }?;
 
@@ -114,14 +229,14 @@ impl Message {
     }
 
     /// parse the unsubscription message.
-    fn from_unsubscribe(res: &[parser::Response]) -> crate::Result<Self> {
+    fn from_unsubscribe(res: &[Response]) -> crate::Result<Self> {
         let channel = match res.get(1) {
-            Some(parser::Response::Bulk(channel)) => Ok((*channel).clone()),
-            _ => Err(ParserError::InvalidChannel),
+            Some(Response::Bulk(channel)) => bulk_to_string(channel),
+            _ => Err(ParserError::InvalidChannel.into()),
         }?;
 
         let subscriptions = match res.get(2) {
-            Some(parser::Response::Integer(subscriptions)) => Ok(*subscriptions),
+            Some(Response::Integer(subscriptions)) => Ok(*subscriptions),
             _ => Err(ParserError::InvalidSubscriberCount),
         }?;
 
@@ -131,14 +246,14 @@ impl Message {
         })
     }
 
-    fn from_punsubscribe(res: &[parser::Response]) -> crate::Result<Self> {
+    fn from_punsubscribe(res: &[Response]) -> crate::Result<Self> {
         let channel = match res.get(1) {
-            Some(parser::Response::Bulk(channel)) => Ok((*channel).clone()),
-            _ => Err(ParserError::InvalidChannel),
+            Some(Response::Bulk(channel)) => bulk_to_string(channel),
+            _ => Err(ParserError::InvalidChannel.into()),
         }?;
 
         let subscriptions = match res.get(2) {
-            Some(parser::Response::Integer(subscriptions)) => Ok(*subscriptions),
+            Some(Response::Integer(subscriptions)) => Ok(*subscriptions),
             _ => Err(ParserError::InvalidSubscriberCount),
         }?;
 
@@ -149,46 +264,92 @@ impl Message {
     }
 
     /// parse the response to a message.
-    fn from_message(res: &[parser::Response]) -> crate::Result<Self> {
+    fn from_message(res: &[Response], metadata: Metadata) -> crate::Result<Self> {
         let channel = match res.get(1) {
-            Some(parser::Response::Bulk(channel)) => Ok((*channel).clone()),
-            _ => Err(ParserError::InvalidChannel),
+            Some(Response::Bulk(channel)) => bulk_to_string(channel),
+            _ => Err(ParserError::InvalidChannel.into()),
         }?;
 
         let message = match res.get(2) {
-            Some(parser::Response::Bulk(message)) => Ok((*message).clone()),
-            _ => Err(ParserError::InvalidSubscriberCount),
+            Some(Response::Bulk(message)) if std::str::from_utf8(message).is_err() => {
+                return Ok(Self::Binary {
+                    pattern: None,
+                    channel,
+                    payload: message.clone(),
+                    metadata,
+                });
+            }
+            Some(Response::Bulk(message)) => bulk_to_string(message),
+            // Invalidations are an array of keys, or null when everything is invalidated.
+            Some(payload) if channel == INVALIDATE_CHANNEL => {
+                return Ok(Self::Invalidation {
+                    invalidation: Invalidation::from_response(payload)?,
+                    metadata,
+                });
+            }
+            _ => Err(ParserError::InvalidSubscriberCount.into()),
         }?;
 
-        Ok(Self::Message { channel, message })
+        Ok(Self::Message {
+            channel,
+            message,
+            metadata,
+        })
     }
 
     /// parse the response to a pattern message
-    fn from_pmessage(res: &[parser::Response]) -> crate::Result<Self> {
+    fn from_pmessage(res: &[Response], metadata: Metadata) -> crate::Result<Self> {
         let pattern = match res.get(1) {
-            Some(parser::Response::Bulk(pattern)) => Ok((*pattern).clone()),
-            _ => Err(ParserError::InvalidPattern),
+            Some(Response::Bulk(pattern)) => bulk_to_string(pattern),
+            _ => Err(ParserError::InvalidPattern.into()),
         }?;
 
         let channel = match res.get(2) {
-            Some(parser::Response::Bulk(channel)) => Ok((*channel).clone()),
-            _ => Err(ParserError::InvalidChannel),
+            Some(Response::Bulk(channel)) => bulk_to_string(channel),
+            _ => Err(ParserError::InvalidChannel.into()),
         }?;
 
         let message = match res.get(3) {
-            Some(parser::Response::Bulk(message)) => Ok((*message).clone()),
-            _ => Err(ParserError::InvalidSubscriberCount),
+            Some(Response::Bulk(message)) if std::str::from_utf8(message).is_err() => {
+                return Ok(Self::Binary {
+                    pattern: Some(pattern),
+                    channel,
+                    payload: message.clone(),
+                    metadata,
+                });
+            }
+            Some(Response::Bulk(message)) => bulk_to_string(message),
+            _ => Err(ParserError::InvalidSubscriberCount.into()),
         }?;
 
         Ok(Self::PatternMessage {
             pattern,
             channel,
             message,
+            metadata,
         })
     }
 }
 
+/// Decode the data of a bulk string as UTF-8.
+fn bulk_to_string(data: &[u8]) -> crate::Result<String> {
+    Ok(std::str::from_utf8(data)?.to_string())
+}
+
 impl Message {
+    /// The metadata of a channel or pattern message, a stream entry or an invalidation.
+    #[must_use]
+    pub const fn metadata(&self) -> Option<&Metadata> {
+        match self {
+            Self::Message { metadata, .. }
+            | Self::PatternMessage { metadata, .. }
+            | Self::Binary { metadata, .. }
+            | Self::Entry { metadata, .. }
+            | Self::Invalidation { metadata, .. } => Some(metadata),
+            _ => None,
+        }
+    }
+
     #[must_use]
     #[inline]
     pub const fn is_subscription(&self) -> bool {
@@ -225,10 +386,57 @@ impl Message {
         matches!(self, Self::PatternMessage { .. })
     }
 
+    /// The channel of a channel or pattern message.
+    #[must_use]
+    pub fn channel(&self) -> Option<&str> {
+        match self {
+            Self::Message { channel, .. }
+            | Self::PatternMessage { channel, .. }
+            | Self::Binary { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+
+    /// The payload of a channel or pattern message.
+    #[must_use]
+    pub fn payload(&self) -> Option<&[u8]> {
+        match self {
+            Self::Message { message, .. } | Self::PatternMessage { message, .. } => {
+                Some(message.as_bytes())
+            }
+            Self::Binary { payload, .. } => Some(payload),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    #[inline]
+    pub const fn is_binary(&self) -> bool {
+        matches!(self, Self::Binary { .. })
+    }
+
+    #[must_use]
+    #[inline]
+    pub const fn is_entry(&self) -> bool {
+        matches!(self, Self::Entry { .. })
+    }
+
+    #[must_use]
+    #[inline]
+    pub const fn is_invalidation(&self) -> bool {
+        matches!(self, Self::Invalidation { .. })
+    }
+
     #[must_use]
     #[inline]
     pub const fn is_connected(&self) -> bool {
-        matches!(self, Self::Connected)
+        matches!(self, Self::Connected { .. })
+    }
+
+    #[must_use]
+    #[inline]
+    pub const fn is_gap(&self) -> bool {
+        matches!(self, Self::Gap { .. })
     }
 
     #[must_use]
@@ -242,4 +450,22 @@ impl Message {
     pub const fn is_error(&self) -> bool {
         matches!(self, Self::Error(_))
     }
+
+    #[must_use]
+    #[inline]
+    pub const fn is_lagged(&self) -> bool {
+        matches!(self, Self::Lagged(_))
+    }
+
+    #[must_use]
+    #[inline]
+    pub const fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed)
+    }
+
+    #[must_use]
+    #[inline]
+    pub const fn is_fatal(&self) -> bool {
+        matches!(self, Self::Fatal(_))
+    }
 }
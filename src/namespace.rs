@@ -0,0 +1,128 @@
+use crate::{glob, Command, Message};
+
+/// Prefix of the channels and patterns on the server, isolating them from other applications.
+///
+/// Channels and patterns starting with `__` are reserved by Redis for the notifications
+/// it publishes itself, like keyspace notifications and invalidations, so they are not prefixed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Namespace {
+    /// Prefix of the channels, empty if there is no namespace.
+    prefix: String,
+}
+
+impl Namespace {
+    pub(crate) fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+
+    /// Whether a channel or pattern is prefixed.
+    fn applies(&self, name: &str) -> bool {
+        !self.prefix.is_empty() && !name.starts_with("__")
+    }
+
+    /// The name of a channel on the server.
+    fn channel(&self, channel: String) -> String {
+        if self.applies(&channel) {
+            format!("{}{}", self.prefix, channel)
+        } else {
+            channel
+        }
+    }
+
+    /// The pattern on the server, which only matches channels in the namespace.
+    fn pattern(&self, pattern: String) -> String {
+        if self.applies(&pattern) {
+            format!("{}{}", glob::escape(&self.prefix), pattern)
+        } else {
+            pattern
+        }
+    }
+
+    /// The name of a key on the server, like the key of a paired stream.
+    pub(crate) fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Prefix the channel or pattern of a command.
+    pub(crate) fn command(&self, command: Command) -> Command {
+        match command {
+            Command::Subscribe(channel) => Command::Subscribe(self.channel(channel)),
+            Command::Unsubscribe(channel) => Command::Unsubscribe(self.channel(channel)),
+            Command::PatternSubscribe(pattern) => Command::PatternSubscribe(self.pattern(pattern)),
+            Command::PatternUnsubscribe(pattern) => {
+                Command::PatternUnsubscribe(self.pattern(pattern))
+            }
+            command => command,
+        }
+    }
+
+    /// Strip the prefix from the channels and patterns of a received message.
+    pub(crate) fn message(&self, mut msg: Message) -> Message {
+        if self.prefix.is_empty() {
+            return msg;
+        }
+
+        let pattern_prefix = glob::escape(&self.prefix);
+        match &mut msg {
+            Message::Subscription { channel, .. }
+            | Message::Unsubscription { channel, .. }
+            | Message::Message { channel, .. } => strip(channel, &self.prefix),
+            Message::PatternSubscription { channel, .. }
+            | Message::PatternUnsubscription { channel, .. } => {
+                strip(channel, &pattern_prefix);
+            }
+            Message::PatternMessage {
+                pattern, channel, ..
+            }
+            | Message::Binary {
+                pattern: Some(pattern),
+                channel,
+                ..
+            } => {
+                strip(pattern, &pattern_prefix);
+                strip(channel, &self.prefix);
+            }
+            Message::Binary { channel, .. } => strip(channel, &self.prefix),
+            _ => {}
+        }
+
+        msg
+    }
+}
+
+/// Strip the prefix of a channel or pattern received from the server.
+fn strip(name: &mut String, prefix: &str) {
+    if let Some(stripped) = name.strip_prefix(prefix) {
+        *name = stripped.to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespace() {
+        let namespace = Namespace::new("staging:[42]:");
+        match namespace.command(Command::PatternSubscribe("orders.*".to_string())) {
+            Command::PatternSubscribe(pattern) => assert_eq!(pattern, "staging:\\[42\\]:orders.*"),
+            command => panic!("command was not `PatternSubscribe`: {:?}", command),
+        }
+        match namespace.command(Command::Subscribe("__redis__:invalidate".to_string())) {
+            Command::Subscribe(channel) => assert_eq!(channel, "__redis__:invalidate"),
+            command => panic!("command was not `Subscribe`: {:?}", command),
+        }
+        assert_eq!(namespace.key("orders-stream"), "staging:[42]:orders-stream");
+
+        let msg = namespace.message(Message::PatternSubscription {
+            channel: "staging:\\[42\\]:orders.*".to_string(),
+            subscriptions: 1,
+        });
+        match msg {
+            Message::PatternSubscription { channel, .. } => assert_eq!(channel, "orders.*"),
+            msg => panic!("message was not `PatternSubscription`: {:?}", msg),
+        }
+    }
+}
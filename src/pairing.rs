@@ -0,0 +1,338 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::{Message, StreamId};
+
+/// Field of a stream entry holding the message.
+pub(crate) const MESSAGE_FIELD: &str = "message";
+
+/// Amount of live messages held for a channel while catching up.
+const HELD_CAPACITY: usize = 1024;
+
+/// A stream which receives the same messages as a channel.
+#[derive(Debug)]
+struct Pairing {
+    /// Key of the stream.
+    key: String,
+    /// ID of the last delivered message.
+    last_id: Option<StreamId>,
+    /// ID of the last entry when the channel was first subscribed to,
+    /// to catch up from when no message is delivered before a disconnect.
+    start: Option<StreamId>,
+}
+
+/// Streams paired with channels, keyed by the channel.
+#[derive(Debug, Default)]
+pub(crate) struct Pairings(Mutex<HashMap<String, Pairing>>);
+
+impl Pairings {
+    /// Pair a channel with a stream.
+    pub(crate) fn insert(&self, channel: String, key: String) {
+        let mut pairings = self.0.lock().unwrap();
+
+        // Keep the position when pairing the same stream again.
+        match pairings.get(&channel) {
+            Some(pairing) if pairing.key == key => {}
+            _ => {
+                pairings.insert(
+                    channel,
+                    Pairing {
+                        key,
+                        last_id: None,
+                        start: None,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Remove the pairing of a channel.
+    pub(crate) fn remove(&self, channel: &str) {
+        self.0.lock().unwrap().remove(channel);
+    }
+
+    /// Remove all pairings.
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// The stream of a channel and the ID to catch up from,
+    /// if the channel is paired and its position in the stream is known.
+    pub(crate) fn catch_up_from(&self, channel: &str) -> Option<(String, StreamId)> {
+        let pairings = self.0.lock().unwrap();
+        let pairing = pairings.get(channel)?;
+
+        Some((pairing.key.clone(), pairing.last_id.or(pairing.start)?))
+    }
+
+    /// The stream of a channel, if the channel is paired and its position in the stream is unknown.
+    pub(crate) fn unstarted(&self, channel: &str) -> Option<String> {
+        let pairings = self.0.lock().unwrap();
+        let pairing = pairings.get(channel)?;
+
+        (pairing.last_id.is_none() && pairing.start.is_none()).then(|| pairing.key.clone())
+    }
+
+    /// Record the last entry of the stream of a channel, when it was first subscribed to.
+    pub(crate) fn set_start(&self, channel: &str, id: StreamId) {
+        if let Some(pairing) = self.0.lock().unwrap().get_mut(channel) {
+            pairing.start.get_or_insert(id);
+        }
+    }
+
+    /// Record the delivery of a message, returns `false` if it was delivered before.
+    pub(crate) fn advance(&self, channel: &str, id: StreamId) -> bool {
+        let mut pairings = self.0.lock().unwrap();
+        let pairing = match pairings.get_mut(channel) {
+            Some(pairing) => pairing,
+            None => return true,
+        };
+
+        if pairing.last_id.is_some_and(|last_id| id <= last_id) {
+            return false;
+        }
+
+        pairing.last_id = Some(id);
+        true
+    }
+
+    /// Strip the stream ID from a message of a paired channel.
+    ///
+    /// Returns `None` if the message was delivered before.
+    /// Messages without a valid ID are passed on unchanged.
+    pub(crate) fn accept(&self, msg: Message) -> Option<Message> {
+        let (channel, message, mut metadata) = match msg {
+            Message::Message {
+                channel,
+                message,
+                metadata,
+            } if self.0.lock().unwrap().contains_key(&channel) => (channel, message, metadata),
+            msg => return Some(msg),
+        };
+
+        let (id, payload) = match split_id(&message) {
+            Some(split) => split,
+            None => {
+                warn!(
+                    "message on paired channel {} has no stream ID, passing it on without deduplication",
+                    channel
+                );
+                return Some(Message::Message {
+                    channel,
+                    message,
+                    metadata,
+                });
+            }
+        };
+
+        if !self.advance(&channel, id) {
+            debug!("dropping duplicate message {} on channel {}", id, channel);
+            return None;
+        }
+
+        metadata.stream_id = Some(id);
+        Some(Message::Message {
+            channel,
+            message: payload.to_string(),
+            metadata,
+        })
+    }
+}
+
+/// A read from a paired stream, on a separate connection.
+#[derive(Debug, Clone)]
+pub(crate) enum CatchUp {
+    /// Read the entries after an ID, which were missed while disconnected.
+    Missed {
+        channel: String,
+        key: String,
+        after: StreamId,
+        /// Amount of failed attempts before this one.
+        attempt: u32,
+    },
+    /// Find the last entry, to catch up from when no message is delivered before a disconnect.
+    Start { channel: String, key: String },
+}
+
+/// Live messages of a channel with missed entries.
+#[derive(Debug, Default)]
+struct Held {
+    /// The held messages, in the order they were received.
+    messages: Vec<Message>,
+    /// When held messages were first dropped, as too many were received.
+    dropped_since: Option<SystemTime>,
+}
+
+/// The catch ups of a connection, which run one at a time while the connection is read.
+#[derive(Debug, Default)]
+pub(crate) struct CatchUps {
+    /// Catch ups waiting for the running one.
+    queue: VecDeque<CatchUp>,
+    /// Live messages of the channels with missed entries, delivered after these entries.
+    held: HashMap<String, Held>,
+}
+
+impl CatchUps {
+    /// Queue a catch up, holding the live messages of its channel if it reads missed entries.
+    pub(crate) fn push(&mut self, catch_up: CatchUp) {
+        if let CatchUp::Missed { channel, .. } = &catch_up {
+            self.held.entry(channel.clone()).or_default();
+        }
+        self.queue.push_back(catch_up);
+    }
+
+    /// Run a catch up next, like reading the next page of entries.
+    pub(crate) fn resume(&mut self, catch_up: CatchUp) {
+        self.queue.push_front(catch_up);
+    }
+
+    /// Take the next catch up to run.
+    pub(crate) fn next(&mut self) -> Option<CatchUp> {
+        self.queue.pop_front()
+    }
+
+    /// Hold a live message of a channel with missed entries, returns other messages.
+    ///
+    /// The held messages are dropped once there are too many of them,
+    /// which is reported by a [Message::Gap] when they are released.
+    pub(crate) fn hold(&mut self, msg: Message) -> Option<Message> {
+        if let Message::Message { channel, .. } = &msg {
+            if let Some(held) = self.held.get_mut(channel) {
+                if held.messages.len() >= HELD_CAPACITY {
+                    warn!(
+                        "dropping {} live messages of channel {} while catching up",
+                        held.messages.len(),
+                        channel
+                    );
+                    held.messages.clear();
+                    held.dropped_since.get_or_insert_with(SystemTime::now);
+                }
+                held.messages.push(msg);
+                return None;
+            }
+        }
+        Some(msg)
+    }
+
+    /// Stop holding the live messages of a channel, returning the held ones,
+    /// after a [Message::Gap] if some were dropped.
+    pub(crate) fn release(&mut self, channel: &str) -> Vec<Message> {
+        let held = match self.held.remove(channel) {
+            Some(held) => held,
+            None => return Vec::new(),
+        };
+
+        let gap = held.dropped_since.map(|since| Message::Gap {
+            channels: vec![channel.to_string()],
+            patterns: Vec::new(),
+            since,
+            until: SystemTime::now(),
+        });
+        gap.into_iter().chain(held.messages).collect()
+    }
+}
+
+/// Split a message published as `<stream ID> <message>`.
+///
+/// The ID must be complete, so messages starting with a number are not mistaken for one.
+fn split_id(message: &str) -> Option<(StreamId, &str)> {
+    let (id, payload) = message.split_once(' ')?;
+
+    Some((StreamId::parse_complete(id).ok()?, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+
+    fn message(message: &str) -> Message {
+        Message::Message {
+            channel: "paired".to_string(),
+            message: message.to_string(),
+            metadata: Metadata::test(),
+        }
+    }
+
+    #[test]
+    fn deduplicates() {
+        let pairings = Pairings::default();
+        pairings.insert("paired".to_string(), "stream".to_string());
+        assert_eq!(pairings.catch_up_from("paired"), None);
+        assert_eq!(pairings.unstarted("paired"), Some("stream".to_string()));
+        pairings.set_start("paired", StreamId { ms: 1, seq: 0 });
+        assert_eq!(pairings.unstarted("paired"), None);
+        assert_eq!(
+            pairings.catch_up_from("paired"),
+            Some(("stream".to_string(), StreamId { ms: 1, seq: 0 }))
+        );
+
+        match pairings.accept(message("1-1 hello world")) {
+            Some(Message::Message {
+                message, metadata, ..
+            }) => {
+                assert_eq!(message, "hello world");
+                assert_eq!(metadata.stream_id, Some(StreamId { ms: 1, seq: 1 }));
+            }
+            msg => panic!("message was not accepted: {:?}", msg),
+        }
+        assert!(pairings.accept(message("1-1 hello world")).is_none());
+        assert!(pairings.accept(message("1-0 older")).is_none());
+        assert!(!pairings.advance("paired", StreamId { ms: 1, seq: 1 }));
+        assert!(pairings.advance("paired", StreamId { ms: 1, seq: 2 }));
+        assert_eq!(
+            pairings.catch_up_from("paired"),
+            Some(("stream".to_string(), StreamId { ms: 1, seq: 2 }))
+        );
+
+        // Messages without an ID are passed on unchanged.
+        assert!(pairings.accept(message("no-id")).is_some());
+        match pairings.accept(message("5 apples")) {
+            Some(Message::Message { message, .. }) => assert_eq!(message, "5 apples"),
+            msg => panic!("message was not passed on: {:?}", msg),
+        }
+        assert_eq!(
+            pairings.catch_up_from("paired"),
+            Some(("stream".to_string(), StreamId { ms: 1, seq: 2 }))
+        );
+    }
+
+    #[test]
+    fn holds_messages() {
+        let mut catch_ups = CatchUps::default();
+        catch_ups.push(CatchUp::Missed {
+            channel: "paired".to_string(),
+            key: "stream".to_string(),
+            after: StreamId::default(),
+            attempt: 0,
+        });
+
+        assert!(catch_ups.hold(message("1-1 live")).is_none());
+        assert!(catch_ups.hold(Message::Closed).is_some());
+        assert!(catch_ups.next().is_some());
+        assert_eq!(catch_ups.release("paired").len(), 1);
+        assert!(catch_ups.hold(message("1-2 live")).is_some());
+    }
+
+    #[test]
+    fn drops_held_messages() {
+        let mut catch_ups = CatchUps::default();
+        catch_ups.push(CatchUp::Missed {
+            channel: "paired".to_string(),
+            key: "stream".to_string(),
+            after: StreamId::default(),
+            attempt: 0,
+        });
+
+        for _ in 0..=HELD_CAPACITY {
+            assert!(catch_ups.hold(message("1-1 live")).is_none());
+        }
+        match catch_ups.release("paired").as_slice() {
+            [Message::Gap { channels, .. }, Message::Message { .. }] => {
+                assert_eq!(channels, &["paired".to_string()]);
+            }
+            released => panic!("dropped messages were not reported: {:?}", released),
+        }
+    }
+}
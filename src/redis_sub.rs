@@ -1,21 +1,73 @@
-use std::cmp;
-use std::collections::HashSet;
-use std::time::Duration;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use async_stream::stream;
-use rand::{thread_rng, Rng};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::{
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-        TcpStream,
-    },
-    sync::Mutex,
-    time::sleep,
+    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
+    sync::{broadcast, watch, Mutex},
+    time::{sleep, sleep_until, Instant},
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::cache::Evict;
+use crate::connection::{
+    backoff, connect, connect_with_backoff, BoxFuture, Initializers, RetryPolicy,
+};
+use crate::events::Listeners;
+use crate::filter::DEFAULT_SEPARATOR;
+use crate::namespace::Namespace;
+use crate::pairing::{CatchUp, CatchUps, Pairings, MESSAGE_FIELD};
+use crate::subscription::{CloseRoutes, Routes, Target};
+use crate::{
+    glob, resp, BroadcastReceiver, Command, Connection, ConnectionListener, InvalidatingCache,
+    Invalidation, Limits, Message, Metadata, StreamEntry, StreamId, Subscription,
+    INVALIDATE_CHANNEL,
 };
-use tokio_stream::Stream;
 
-use crate::{parser, Command, Message};
+/// Default amount of messages a broadcast receiver can fall behind.
+const BROADCAST_CAPACITY: usize = 1024;
+
+/// Default amount of messages a subscription handle can fall behind.
+const SUBSCRIPTION_CAPACITY: usize = 1024;
+
+/// Amount of stream entries read at once when catching up.
+const CATCH_UP_BATCH: usize = 1024;
+
+/// Amount of attempts to catch up on a stream before reporting a gap.
+const CATCH_UP_ATTEMPTS: u32 = 5;
+
+/// What to do when incoming data exceeds the configured [Limits].
+///
+/// Other malformed data always drops the connection, as the next frame cannot be found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the offending message and yield a [Message::Error], keeping the connection.
+    #[default]
+    Skip,
+    /// Drop the connection and reconnect, yielding a [Message::Disconnected].
+    Reconnect,
+}
+
+/// Progress of a graceful shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShutdownState {
+    /// No stream is listening.
+    Idle,
+    /// The stream is listening.
+    Running,
+    /// The stream is waiting for unsubscribe acknowledgements until the deadline.
+    Draining {
+        deadline: Instant,
+        pending_acks: bool,
+    },
+    /// The stream has ended after a shutdown.
+    Closed,
+}
 
 /// Redis subscription object.
 /// This connects to the Redis server.
@@ -23,12 +75,46 @@ use crate::{parser, Command, Message};
 pub struct RedisSub {
     /// Address of the redis server.
     addr: String,
-    /// Set of channels currently subscribed to.
-    channels: Mutex<HashSet<String>>,
-    /// Set of channels currently subscribed to by pattern.
-    pattern_channels: Mutex<HashSet<String>>,
+    /// Channels currently subscribed to, with the amount of subscriptions to them.
+    channels: Mutex<HashMap<String, usize>>,
+    /// Patterns currently subscribed to, with the amount of subscriptions to them.
+    pattern_channels: Mutex<HashMap<String, usize>>,
     /// TCP socket writer to write commands to.
-    writer: Mutex<Option<OwnedWriteHalf>>,
+    pub(crate) writer: Mutex<Option<OwnedWriteHalf>>,
+    /// Limits enforced on the incoming data.
+    limits: Limits,
+    /// What to do when the limits are exceeded.
+    overflow_policy: OverflowPolicy,
+    /// Sender for the broadcast receivers.
+    broadcast: broadcast::Sender<Message>,
+    /// Routes to the subscription handles.
+    pub(crate) routes: Routes,
+    /// Progress of a graceful shutdown.
+    shutdown: watch::Sender<ShutdownState>,
+    /// Decides whether to keep retrying after an error.
+    retry_policy: RetryPolicy,
+    /// Listeners for connection lifecycle events.
+    listeners: Listeners,
+    /// Functions run on every new connection.
+    initializers: Initializers,
+    /// Streams paired with channels to catch up on missed messages.
+    pairings: Pairings,
+    /// Connection reading the paired streams, kept between catch ups.
+    catch_up_conn: Mutex<Option<Connection>>,
+    /// Name set with `CLIENT SETNAME` on every new connection.
+    client_name: Option<String>,
+    /// Amount of connections made, numbering the connections.
+    generation: AtomicU64,
+    /// ID of the current connection, if connected and supported by the server.
+    client_id: std::sync::Mutex<Option<i64>>,
+    /// Whether invalidations of client-side caching are received.
+    tracking: bool,
+    /// Local caches evicted by invalidations.
+    caches: Vec<Arc<dyn Evict>>,
+    /// Prefix of the channels and patterns on the server.
+    namespace: Namespace,
+    /// Separator of the levels of hierarchical topics.
+    pub(crate) topic_separator: char,
 }
 
 impl RedisSub {
@@ -38,56 +124,398 @@ impl RedisSub {
     pub fn new(addr: &str) -> Self {
         Self {
             addr: addr.to_string(),
-            channels: Mutex::new(HashSet::new()),
-            pattern_channels: Mutex::new(HashSet::new()),
+            channels: Mutex::new(HashMap::new()),
+            pattern_channels: Mutex::new(HashMap::new()),
             writer: Mutex::new(None),
+            limits: Limits::default(),
+            overflow_policy: OverflowPolicy::default(),
+            broadcast: broadcast::channel(BROADCAST_CAPACITY).0,
+            routes: Routes::new(SUBSCRIPTION_CAPACITY),
+            shutdown: watch::channel(ShutdownState::Idle).0,
+            retry_policy: RetryPolicy::default(),
+            listeners: Listeners::default(),
+            initializers: Initializers::default(),
+            pairings: Pairings::default(),
+            catch_up_conn: Mutex::new(None),
+            client_name: None,
+            generation: AtomicU64::new(0),
+            client_id: std::sync::Mutex::new(None),
+            tracking: false,
+            caches: Vec::new(),
+            namespace: Namespace::default(),
+            topic_separator: DEFAULT_SEPARATOR,
+        }
+    }
+
+    /// Add a listener for connection lifecycle events.
+    #[must_use]
+    pub fn with_listener<L>(mut self, listener: L) -> Self
+    where
+        L: ConnectionListener + 'static,
+    {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
+    /// Set the client name shown by `CLIENT LIST` on the server.
+    ///
+    /// The process ID is appended to the name, like `name-1234`,
+    /// so instances of the same service can be told apart.
+    /// The name is set on every new connection, before the initializers run.
+    ///
+    /// # Errors
+    /// Returns an error if the name contains spaces, newlines or other characters rejected by the server.
+    pub fn with_client_name(mut self, name: &str) -> crate::Result<Self> {
+        if !name.chars().all(|c| ('!'..='~').contains(&c)) {
+            return Err(crate::Error::InvalidClientName(name.to_string()));
+        }
+
+        self.client_name = Some(format!("{}-{}", name, std::process::id()));
+        Ok(self)
+    }
+
+    /// Add a function which is run on every new connection, before the stored channels are subscribed to.
+    ///
+    /// It can send commands like `CLIENT SETNAME` or `SELECT` with [Connection::command].
+    /// Returning an error aborts the connection attempt, which is retried according to the retry policy.
+    /// Initializers run in the order they are added.
+    #[must_use]
+    pub fn with_initializer<F>(mut self, initializer: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut Connection) -> BoxFuture<'a, crate::Result<()>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.initializers.push(Box::new(initializer));
+        self
+    }
+
+    /// Receive the invalidations of client-side caching, as [Message::Invalidation]s.
+    ///
+    /// This subscribes to the invalidation channel on every connection.
+    /// Enable tracking on the connection used for reads with `CLIENT TRACKING on REDIRECT <id>`,
+    /// using the ID from `.client_id()` or [Message::Connected].
+    /// The ID changes on every reconnect, so tracking must then be enabled again,
+    /// and as invalidations may have been missed, an [Invalidation::Flush] is yielded.
+    /// Caches without channels only cache values again after [InvalidatingCache::confirm_tracking].
+    #[must_use]
+    pub fn with_tracking_invalidation(mut self) -> Self {
+        if !self.tracking {
+            acquire(self.channels.get_mut(), INVALIDATE_CHANNEL);
+            self.tracking = true;
         }
+        self
+    }
+
+    /// Evict values from a local cache when invalidations are received.
+    ///
+    /// The channels of the cache are subscribed to, see [InvalidatingCache].
+    #[must_use]
+    pub fn with_cache<K, V>(mut self, cache: Arc<InvalidatingCache<K, V>>) -> Self
+    where
+        K: Borrow<str> + Eq + Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        for channel in cache.channels() {
+            acquire(self.channels.get_mut(), channel);
+        }
+        self.caches.push(cache);
+        self
+    }
+
+    /// Set the function deciding whether to keep retrying after an error.
+    ///
+    /// Returning `false` ends the stream with a [Message::Fatal].
+    /// By default retrying stops on errors for which [Error::is_fatal] returns `true`.
+    ///
+    /// [Error::is_fatal]: crate::Error::is_fatal
+    #[must_use]
+    pub fn with_retry_policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&crate::Error) -> bool + Send + Sync + 'static,
+    {
+        self.retry_policy = RetryPolicy::new(policy);
+        self
+    }
+
+    /// Isolate the channels and patterns with a prefix, like `staging:tenant42:`.
+    ///
+    /// The prefix is added to the channels and patterns sent to the server,
+    /// and stripped from the channels and patterns of the received messages,
+    /// so the application only uses the names without the prefix.
+    /// Channels and patterns starting with `__` are reserved by Redis and not prefixed,
+    /// like the channels of keyspace notifications and invalidations.
+    /// The keys of the streams paired by `.subscribe_with_stream()` are prefixed too.
+    #[must_use]
+    pub fn with_namespace(mut self, prefix: &str) -> Self {
+        self.namespace = Namespace::new(prefix);
+        self
+    }
+
+    /// Set the separator of the levels of hierarchical topics, `/` by default.
+    ///
+    /// See [TopicFilter].
+    ///
+    /// [TopicFilter]: crate::TopicFilter
+    #[must_use]
+    pub fn with_topic_separator(mut self, separator: char) -> Self {
+        self.topic_separator = separator;
+        self
+    }
+
+    /// Set the limits enforced on data received from the server.
+    #[must_use]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set what happens when data received from the server exceeds the limits.
+    #[must_use]
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Set the amount of messages a broadcast receiver can fall behind before missing messages.
+    ///
+    /// # Panics
+    /// Panics if the capacity is zero.
+    #[must_use]
+    pub fn with_broadcast_capacity(mut self, capacity: usize) -> Self {
+        self.broadcast = broadcast::channel(capacity).0;
+        self
+    }
+
+    /// Set the amount of messages a subscription handle can fall behind before missing messages.
+    ///
+    /// # Panics
+    /// Panics if the capacity is zero.
+    #[must_use]
+    pub fn with_subscription_capacity(mut self, capacity: usize) -> Self {
+        self.routes = Routes::new(capacity);
+        self
+    }
+
+    /// The client ID of the current connection, to redirect tracking to.
+    ///
+    /// Returns `None` while disconnected, or if the server does not support `CLIENT ID`.
+    #[must_use]
+    pub fn client_id(&self) -> Option<i64> {
+        *self.client_id.lock().unwrap()
+    }
+
+    /// Create a receiver which gets a copy of every message yielded by the stream.
+    ///
+    /// Any amount of receivers can be created, while a single connection is used.
+    /// Messages are only received while the stream returned by `.listen()` is polled,
+    /// for example by a background task from `.spawn()`.
+    #[must_use]
+    pub fn broadcast_receiver(&self) -> BroadcastReceiver {
+        BroadcastReceiver::new(self.broadcast.subscribe())
     }
 
     /// Subscribe to a channel.
     ///
+    /// Subscriptions are counted: subscribing to a channel multiple times subscribes once,
+    /// and it stays subscribed until every subscription is released.
+    ///
     /// # Errors
     /// Returns an error if an error happens on the underlying TCP stream.
     pub async fn subscribe(&self, channel: String) -> crate::Result<()> {
-        self.channels.lock().await.insert(channel.clone());
+        let mut channels = self.channels.lock().await;
+        if !acquire(&mut channels, &channel) {
+            return Ok(());
+        }
 
         self.send_cmd(Command::Subscribe(channel)).await
     }
 
     /// Unsubscribe from a channel.
     ///
+    /// The server is only unsubscribed from when this releases the last subscription.
+    ///
     /// # Errors
     /// Returns an error if an error happens on the underlying TCP stream.
     pub async fn unsubscribe(&self, channel: String) -> crate::Result<()> {
-        if !self.channels.lock().await.remove(&channel) {
-            return Err(crate::Error::NotSubscribed);
+        let mut channels = self.channels.lock().await;
+        if !release(&mut channels, &channel)? {
+            return Ok(());
         }
+        self.pairings.remove(&channel);
 
         self.send_cmd(Command::Unsubscribe(channel)).await
     }
 
+    /// Subscribe to a channel which is paired with a stream, so no message is missed.
+    ///
+    /// Every message must be added to the stream in a `message` field, and then published
+    /// on the channel prefixed by the ID of the entry and a space: `<ID> <message>`.
+    /// After reconnecting, the entries added after the last delivered message,
+    /// or after the last entry when the channel was first subscribed to,
+    /// are read from the stream with `XRANGE` on a separate connection, while messages keep being read.
+    /// Messages are delivered in order of their ID, without the prefix,
+    /// and messages which were already delivered are dropped.
+    /// Messages without the prefix are passed on unchanged, without dropping duplicates, and logged.
+    /// If the stream cannot be read after a few attempts,
+    /// or too many live messages arrive while catching up, a [Message::Gap] is yielded for the channel.
+    ///
+    /// Subscriptions are counted like with `.subscribe()`,
+    /// the pairing is removed once the last subscription to the channel is released.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn subscribe_with_stream(
+        &self,
+        channel: String,
+        stream_key: String,
+    ) -> crate::Result<()> {
+        self.pairings
+            .insert(channel.clone(), self.namespace.key(&stream_key));
+        self.subscribe(channel).await
+    }
+
     /// Subscribe to a pattern of channels.
     ///
+    /// Subscriptions are counted like with `.subscribe()`.
+    ///
     /// # Errors
     /// Returns an error if an error happens on the underlying TCP stream.
     pub async fn psubscribe(&self, channel: String) -> crate::Result<()> {
-        self.pattern_channels.lock().await.insert(channel.clone());
+        let mut channels = self.pattern_channels.lock().await;
+        if !acquire(&mut channels, &channel) {
+            return Ok(());
+        }
 
         self.send_cmd(Command::PatternSubscribe(channel)).await
     }
 
     /// Unsubscribe from a pattern of channels.
     ///
+    /// The server is only unsubscribed from when this releases the last subscription.
+    ///
     /// # Errors
     /// Returns an error if an error happens on the underlying TCP stream.
     pub async fn punsubscribe(&self, channel: String) -> crate::Result<()> {
-        if !self.pattern_channels.lock().await.remove(&channel) {
-            return Err(crate::Error::NotSubscribed);
+        let mut channels = self.pattern_channels.lock().await;
+        if !release(&mut channels, &channel)? {
+            return Ok(());
         }
 
         self.send_cmd(Command::PatternUnsubscribe(channel)).await
     }
 
+    /// Subscribe to a channel, returning a handle which receives only its messages.
+    ///
+    /// Dropping the handle releases its subscription, like calling `.unsubscribe()`.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn subscription(self: &Arc<Self>, channel: String) -> crate::Result<Subscription> {
+        let subscription = Subscription::new(self.clone(), Target::Channel(channel.clone()));
+        self.subscribe(channel).await?;
+
+        Ok(subscription)
+    }
+
+    /// Subscribe to a pattern of channels, returning a handle which receives only its messages.
+    ///
+    /// Dropping the handle releases its subscription, like calling `.punsubscribe()`.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn psubscription(self: &Arc<Self>, channel: String) -> crate::Result<Subscription> {
+        let subscription = Subscription::new(self.clone(), Target::Pattern(channel.clone()));
+        self.psubscribe(channel).await?;
+
+        Ok(subscription)
+    }
+
+    /// The subscribed patterns which match a channel, see [glob].
+    ///
+    /// [glob]: crate::glob
+    pub async fn matching_patterns(&self, channel: &str) -> Vec<String> {
+        self.pattern_channels
+            .lock()
+            .await
+            .keys()
+            .filter(|pattern| glob::matches(pattern, channel))
+            .cloned()
+            .collect()
+    }
+
+    /// Release the subscription of a dropped handle or a stopped router.
+    pub(crate) async fn release(&self, target: Target) {
+        let res = match target {
+            Target::Channel(channel) => self.unsubscribe(channel).await,
+            Target::Pattern(channel) => self.punsubscribe(channel).await,
+        };
+
+        match res {
+            // All subscriptions are forgotten after a shutdown.
+            Ok(()) | Err(crate::Error::NotSubscribed) => {}
+            Err(e) => warn!("failed to release subscription: {:?}", e),
+        }
+    }
+
+    /// Gracefully stop listening.
+    ///
+    /// This unsubscribes from all channels and patterns, after which the stream yields
+    /// the messages received before the acknowledgements.
+    /// The connection is then closed with `QUIT`, and the stream ends with a [Message::Closed].
+    ///
+    /// Returns once the stream has ended, or when the timeout has elapsed.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn shutdown(&self, timeout: Duration) -> crate::Result<()> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shutdown.subscribe();
+
+        // Forget all subscriptions, so they are not restored.
+        let mut channels = self.channels.lock().await;
+        let mut patterns = self.pattern_channels.lock().await;
+        let connected = self.writer.lock().await.is_some();
+
+        let listening = self.shutdown.send_if_modified(|state| {
+            if *state != ShutdownState::Running {
+                return false;
+            }
+
+            *state = ShutdownState::Draining {
+                deadline,
+                pending_acks: connected && !(channels.is_empty() && patterns.is_empty()),
+            };
+            true
+        });
+
+        if !channels.is_empty() {
+            self.send_cmd(Command::UnsubscribeAll).await?;
+        }
+        if !patterns.is_empty() {
+            self.send_cmd(Command::PatternUnsubscribeAll).await?;
+        }
+        channels.clear();
+        patterns.clear();
+        self.pairings.clear();
+        drop(channels);
+        drop(patterns);
+
+        if !listening {
+            return Ok(());
+        }
+
+        // Wait for the stream to end.
+        let closed = state.wait_for(|state| *state == ShutdownState::Closed);
+        if tokio::time::timeout_at(deadline, closed).await.is_err() {
+            warn!("stream did not end before the shutdown timeout, closing the connection");
+            *self.writer.lock().await = None;
+        }
+
+        Ok(())
+    }
+
     /// Connect to the Redis server specified by `self.addr`.
     ///
     /// Handles exponential backoff.
@@ -100,47 +528,120 @@ impl RedisSub {
         &self,
         fail_fast: bool,
     ) -> crate::Result<(OwnedReadHalf, OwnedWriteHalf)> {
-        let mut retry_count = 0;
-
-        loop {
-            // Generate jitter for the backoff function.
-            let jitter = thread_rng().gen_range(0..1000);
-            // Connect to the Redis server.
-            match TcpStream::connect(self.addr.as_str()).await {
-                Ok(stream) => return Ok(stream.into_split()),
-                Err(e) if fail_fast => return Err(crate::Error::IoError(e)),
-                Err(e) if retry_count <= 7 => {
-                    // Backoff and reconnect.
-                    warn!(
-                        "failed to connect to redis (attempt {}/8) {:?}",
-                        retry_count, e
-                    );
-                    retry_count += 1;
-                    let timeout = cmp::min(retry_count ^ 2, 64) * 1000 + jitter;
-                    sleep(Duration::from_millis(timeout)).await;
-                    continue;
-                }
-                Err(e) => {
-                    // Retry count has passed 7.
-                    // Assume connection failed and return.
-                    return Err(crate::Error::IoError(e));
-                }
-            };
+        // The fail fast connection only checks the server is reachable, it is not reported.
+        let stream = connect_with_backoff(&self.addr, fail_fast, &self.retry_policy, |attempt| {
+            if !fail_fast {
+                self.listeners.connect_attempt(attempt);
+            }
+        })
+        .await?;
+
+        Ok(stream.into_split())
+    }
+
+    /// Prepare a new connection before anything is subscribed to.
+    ///
+    /// Sets the client name, runs the initializers and returns the client ID,
+    /// which is `None` if the server does not support `CLIENT ID`.
+    async fn initialize(&self, conn: &mut Connection) -> crate::Result<Option<i64>> {
+        if let Some(name) = &self.client_name {
+            conn.command(["CLIENT", "SETNAME", name]).await?;
+        }
+
+        let client_id = conn.client_id().await?;
+        self.initializers.run(conn).await?;
+        Ok(client_id)
+    }
+
+    /// Open and initialize a connection for commands, separate from the subscriptions.
+    pub(crate) async fn side_connection(&self) -> crate::Result<Connection> {
+        let (read, write) = connect(&self.addr).await?.into_split();
+        let mut conn = Connection::new(read, write, self.limits);
+        self.initialize(&mut conn).await?;
+
+        Ok(conn)
+    }
+
+    /// Send a command on the connection reading the paired streams, opening it if needed.
+    ///
+    /// Only the initializers are run on it, as they may be needed to read the streams,
+    /// like selecting the database.
+    async fn catch_up_command(&self, args: [&str; 6]) -> crate::Result<resp::Response> {
+        let mut conn = self.catch_up_conn.lock().await;
+        if conn.is_none() {
+            let (read, write) = connect(&self.addr).await?.into_split();
+            let mut new = Connection::new(read, write, self.limits);
+            self.initializers.run(&mut new).await?;
+            *conn = Some(new);
         }
+
+        let res = conn
+            .as_mut()
+            .expect("catch up connection is open")
+            .command(args)
+            .await;
+        // Open a new connection next time, unless the server just rejected the command.
+        if matches!(&res, Err(e) if !matches!(e, crate::Error::ServerError(_))) {
+            *conn = None;
+        }
+        res
     }
 
-    async fn subscribe_stored(&self) -> crate::Result<()> {
-        for channel in self.channels.lock().await.iter() {
+    /// Read a page of the entries added to a stream after the given ID.
+    ///
+    /// The page is full if more entries may follow.
+    async fn read_missed(&self, key: &str, after: StreamId) -> crate::Result<Vec<StreamEntry>> {
+        let start = after.next().to_string();
+        let count = CATCH_UP_BATCH.to_string();
+        let res = self
+            .catch_up_command(["XRANGE", key, &start, "+", "COUNT", &count])
+            .await?;
+        let entries = StreamEntry::from_range(res)?;
+
+        debug!("read {} missed entries from stream {}", entries.len(), key);
+        Ok(entries)
+    }
+
+    /// Run a catch up, waiting before it if earlier attempts failed.
+    async fn catch_up(&self, catch_up: CatchUp) -> (CatchUp, crate::Result<Vec<StreamEntry>>) {
+        let res = match &catch_up {
+            CatchUp::Missed {
+                key,
+                after,
+                attempt,
+                ..
+            } => {
+                if *attempt > 0 {
+                    sleep(backoff(u64::from(*attempt))).await;
+                }
+                self.read_missed(key, *after).await
+            }
+            CatchUp::Start { key, .. } => self
+                .catch_up_command(["XREVRANGE", key, "+", "-", "COUNT", "1"])
+                .await
+                .and_then(StreamEntry::from_range),
+        };
+        (catch_up, res)
+    }
+
+    /// Subscribe to the stored channels and patterns, returning which ones were subscribed to.
+    async fn subscribe_stored(&self) -> crate::Result<(Vec<String>, Vec<String>)> {
+        let channels = self.channels.lock().await;
+        for channel in channels.keys() {
             self.send_cmd(Command::Subscribe(channel.to_string()))
                 .await?;
         }
 
-        for channel in self.pattern_channels.lock().await.iter() {
+        let patterns = self.pattern_channels.lock().await;
+        for channel in patterns.keys() {
             self.send_cmd(Command::PatternSubscribe(channel.to_string()))
                 .await?;
         }
 
-        Ok(())
+        let channels = channels.keys().cloned().collect::<Vec<_>>();
+        let patterns = patterns.keys().cloned().collect::<Vec<_>>();
+        self.listeners.subscriptions_restored(&channels, &patterns);
+        Ok((channels, patterns))
     }
 
     /// Listen for incoming messages.
@@ -152,15 +653,65 @@ impl RedisSub {
     pub async fn listen(&self) -> crate::Result<impl Stream<Item = Message> + '_> {
         self.connect(true).await?;
 
-        Ok(Box::pin(stream! {
-            loop {
-                let (mut read, write) = match self.connect(false).await {
-                    Ok(t) => t,
-                    Err(e) => {
+        self.shutdown.send_replace(ShutdownState::Running);
+
+        let stream = stream! {
+            // End the subscription handles once the stream ends or is dropped.
+            let _routes = CloseRoutes(&self.routes);
+            let mut state = self.shutdown.subscribe();
+            let mut fatal = None;
+            // When the last connection was lost, until the subscriptions are restored.
+            let mut lost = None;
+
+            'outer: loop {
+                // Stop reconnecting when shutting down.
+                if *state.borrow_and_update() != ShutdownState::Running {
+                    break 'outer;
+                }
+
+                let connected = tokio::select! {
+                    res = self.connect(false) => Some(res),
+                    _ = state.changed() => None,
+                };
+                let (read, write) = match connected {
+                    Some(Ok(t)) => t,
+                    Some(Err(e)) if !self.retry_policy.retries(&e) => {
+                        fatal = Some(e);
+                        break 'outer;
+                    }
+                    Some(Err(e)) => {
                         warn!("failed to connect to server: {:?}", e);
                         continue;
                     }
+                    None => continue,
+                };
+
+                self.listeners.connected();
+
+                // Prepare the connection before anything is subscribed to.
+                let mut conn = Connection::new(read, write, self.limits);
+                let client_id = match self.initialize(&mut conn).await {
+                    Ok(client_id) => client_id,
+                    Err(e) => {
+                        warn!("failed to initialize connection: {:?}", e);
+                        self.listeners.disconnected(&e);
+                        yield Message::Error(e.clone());
+                        if !self.retry_policy.retries(&e) {
+                            fatal = Some(e);
+                            break 'outer;
+                        }
+
+                        // Wait before the next attempt, as the connection itself succeeded.
+                        tokio::select! {
+                            _ = sleep(Duration::from_secs(1)) => {}
+                            _ = state.changed() => {}
+                        }
+                        continue;
+                    }
                 };
+                let (mut read, write, mut parser) = conn.into_parts();
+                let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+                *self.client_id.lock().unwrap() = client_id;
 
                 // Update the stored writer.
                 {
@@ -171,25 +722,171 @@ impl RedisSub {
 
                 // Subscribe to all stored channels
                 debug!("subscribing to stored channels after connect");
-                if let Err(e) = self.subscribe_stored().await {
-                    warn!("failed to subscribe to stored channels on connection, trying connection again... (err {:?})", e);
-                    continue;
-                }
+                let (channels, patterns) = match self.subscribe_stored().await {
+                    Ok(restored) => restored,
+                    Err(e) => {
+                        *self.writer.lock().await = None;
+                        self.listeners.disconnected(&e);
+                        if !self.retry_policy.retries(&e) {
+                            fatal = Some(e);
+                            break 'outer;
+                        }
+
+                        warn!("failed to subscribe to stored channels on connection, trying connection again... (err {:?})", e);
+                        continue;
+                    }
+                };
 
                 // Yield a connect message to the library consumer.
-                yield Message::Connected;
+                yield Message::Connected { client_id };
 
-                // Create the read buffers.
+                // Report the messages which may have been missed while disconnected.
+                let lost_since = lost.take();
+                if let Some(since) = lost_since {
+                    if !(channels.is_empty() && patterns.is_empty()) {
+                        yield Message::Gap {
+                            channels,
+                            patterns,
+                            since,
+                            until: SystemTime::now(),
+                        };
+                    }
+                }
+
+                // Tracking is not redirected to the new connection, so everything may be stale.
+                let mut sequence = 0;
+                if self.tracking && generation > 1 {
+                    yield Message::Invalidation {
+                        invalidation: Invalidation::Flush,
+                        metadata: Metadata {
+                            received: std::time::Instant::now(),
+                            timestamp: SystemTime::now(),
+                            generation,
+                            sequence,
+                            stream_id: None,
+                        },
+                    };
+                    sequence += 1;
+                }
+
+                // Create the read buffer, the parser keeps the data left by the initializers.
                 let mut buf = [0; 64 * 1024];
-                let mut unread_buf = String::new();
+                let mut drained = false;
+                // Catch ups on paired streams run one at a time, while the connection is read.
+                let mut catch_ups = CatchUps::default();
+                let mut catching_up: Option<BoxFuture<'_, (CatchUp, crate::Result<Vec<StreamEntry>>)>> = None;
 
                 'inner: loop {
+                    // Stop once the unsubscriptions are acknowledged, or the deadline passed.
+                    let deadline = match *state.borrow_and_update() {
+                        ShutdownState::Draining { pending_acks: false, .. } => break 'outer,
+                        ShutdownState::Draining { .. } if drained => break 'outer,
+                        ShutdownState::Draining { deadline, .. } => Some(deadline),
+                        _ => None,
+                    };
+
                     debug!("reading incoming data");
                     // Read incoming data to the buffer.
-                    let res = match read.read(&mut buf).await {
-                        Ok(0) => Err(crate::Error::ZeroBytesRead),
-                        Ok(n) => Ok(n),
-                        Err(e) => Err(crate::Error::from(e)),
+                    let incoming = tokio::select! {
+                        res = read.read(&mut buf) => Incoming::Read(match res {
+                            Ok(0) => Err(crate::Error::ZeroBytesRead),
+                            Ok(n) => Ok(n),
+                            Err(e) => Err(crate::Error::from(e)),
+                        }),
+                        (catch_up, res) = running(&mut catching_up) => Incoming::CaughtUp(catch_up, res),
+                        _ = state.changed() => continue 'inner,
+                        _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                            warn!("shutdown deadline passed before all unsubscriptions were acknowledged");
+                            break 'outer;
+                        }
+                    };
+
+                    let res = match incoming {
+                        Incoming::Read(res) => res,
+                        Incoming::CaughtUp(catch_up, res) => {
+                            let caught_up = match (catch_up, res) {
+                                (CatchUp::Start { channel, .. }, Ok(entries)) => {
+                                    let start = entries.first().map_or_else(StreamId::default, |entry| entry.id);
+                                    self.pairings.set_start(&channel, start);
+                                    None
+                                }
+                                (CatchUp::Start { key, .. }, Err(e)) => {
+                                    warn!("failed to read the last entry of stream {}: {:?}", key, e);
+                                    None
+                                }
+                                (CatchUp::Missed { channel, key, .. }, Ok(entries)) => {
+                                    // Read the next page once this one is delivered.
+                                    let next_page = match entries.last() {
+                                        Some(entry) if entries.len() == CATCH_UP_BATCH => Some(entry.id),
+                                        _ => None,
+                                    };
+                                    for entry in entries {
+                                        if !self.pairings.advance(&channel, entry.id) {
+                                            continue;
+                                        }
+                                        let message = match entry.get(MESSAGE_FIELD) {
+                                            Some(message) => message.to_string(),
+                                            None => {
+                                                warn!("stream entry {} has no message field", entry.id);
+                                                continue;
+                                            }
+                                        };
+
+                                        yield Message::Message {
+                                            channel: channel.clone(),
+                                            message,
+                                            metadata: Metadata {
+                                                received: std::time::Instant::now(),
+                                                timestamp: SystemTime::now(),
+                                                generation,
+                                                sequence,
+                                                stream_id: Some(entry.id),
+                                            },
+                                        };
+                                        sequence += 1;
+                                    }
+                                    match next_page {
+                                        Some(after) => {
+                                            catch_ups.resume(CatchUp::Missed { channel, key, after, attempt: 0 });
+                                            None
+                                        }
+                                        None => Some(channel),
+                                    }
+                                }
+                                (CatchUp::Missed { channel, key, after, attempt }, Err(e)) if attempt + 1 < CATCH_UP_ATTEMPTS => {
+                                    warn!("failed to catch up on stream {}, retrying: {:?}", key, e);
+                                    catch_ups.push(CatchUp::Missed { channel, key, after, attempt: attempt + 1 });
+                                    None
+                                }
+                                (CatchUp::Missed { channel, key, .. }, Err(e)) => {
+                                    warn!("giving up catching up on stream {}: {:?}", key, e);
+                                    yield Message::Error(e);
+                                    yield Message::Gap {
+                                        channels: vec![channel.clone()],
+                                        patterns: Vec::new(),
+                                        since: lost_since.unwrap_or_else(SystemTime::now),
+                                        until: SystemTime::now(),
+                                    };
+                                    Some(channel)
+                                }
+                            };
+
+                            // Deliver the live messages received while catching up.
+                            if let Some(channel) = caught_up {
+                                for msg in catch_ups.release(&channel) {
+                                    if let Some(mut msg) = self.pairings.accept(msg) {
+                                        if let Message::Message { metadata, .. } = &mut msg {
+                                            metadata.sequence = sequence;
+                                            sequence += 1;
+                                        }
+                                        yield msg;
+                                    }
+                                }
+                            }
+
+                            catching_up = catch_ups.next().map(|catch_up| Box::pin(self.catch_up(catch_up)) as BoxFuture<'_, _>);
+                            continue 'inner;
+                        }
                     };
 
                     // Disconnect and reconnect if a write error occurred.
@@ -197,30 +894,129 @@ impl RedisSub {
                         Ok(n) => n,
                         Err(e) => {
                             *self.writer.lock().await = None;
-                            yield Message::Disconnected(e);
+                            self.listeners.disconnected(&e);
+                            lost.get_or_insert_with(SystemTime::now);
+                            *self.client_id.lock().unwrap() = None;
+                            yield Message::Disconnected(e.clone());
+                            if !self.retry_policy.retries(&e) {
+                                fatal = Some(e);
+                                break 'outer;
+                            }
                             break 'inner;
                         }
                     };
 
-                    let buf_data = match std::str::from_utf8(&buf[..n]) {
-                        Ok(d) => d,
-                        Err(e) => {
-                            yield Message::Error(e.into());
-                            continue;
-                        }
-                    };
-
-                    // Add the new data to the unread buffer.
-                    unread_buf.push_str(buf_data);
-                    // Parse the unread data.
-                    let parsed = parser::parse(&mut unread_buf);
+                    // Add the new data to the parser buffer.
+                    parser.feed(&buf[..n]);
+                    let received = std::time::Instant::now();
+                    let timestamp = SystemTime::now();
 
                     // Loop through the parsed commands.
-                    for res in parsed {
+                    loop {
+                        let res = match parser.next_response() {
+                            Ok(Some(res)) => res,
+                            Ok(None) => break,
+                            // Other errors leave the parser out of sync, so only exceeded limits are skipped.
+                            Err(e @ crate::ParserError::LimitExceeded(_)) if self.overflow_policy == OverflowPolicy::Skip => {
+                                warn!("skipping incoming message: {:?}", e);
+                                if let Err(e) = parser.skip_frame() {
+                                    let e = crate::Error::from(e);
+                                    *self.writer.lock().await = None;
+                                    self.listeners.disconnected(&e);
+                                    lost.get_or_insert_with(SystemTime::now);
+                                    *self.client_id.lock().unwrap() = None;
+                                    yield Message::Disconnected(e.clone());
+                                    if !self.retry_policy.retries(&e) {
+                                        fatal = Some(e);
+                                        break 'outer;
+                                    }
+                                    break 'inner;
+                                }
+
+                                yield Message::Error(e.into());
+                                continue;
+                            }
+                            Err(e) => {
+                                warn!("dropping connection: {:?}", e);
+                                let e = crate::Error::from(e);
+                                *self.writer.lock().await = None;
+                                self.listeners.disconnected(&e);
+                                lost.get_or_insert_with(SystemTime::now);
+                                *self.client_id.lock().unwrap() = None;
+                                yield Message::Disconnected(e.clone());
+                                if !self.retry_policy.retries(&e) {
+                                    fatal = Some(e);
+                                    break 'outer;
+                                }
+                                break 'inner;
+                            }
+                        };
+
                         debug!("new message");
                         // Create a message from the parsed command and yield it.
-                        match Message::from_response(res) {
-                            Ok(msg) => yield msg,
+                        let metadata = Metadata {
+                            received,
+                            timestamp,
+                            generation,
+                            sequence,
+                            stream_id: None,
+                        };
+                        match Message::from_response(res, metadata) {
+                            Ok(msg) => {
+                                let msg = self.namespace.message(msg);
+                                // Hold the live messages of channels which are catching up.
+                                let msg = match catch_ups.hold(msg) {
+                                    Some(msg) => msg,
+                                    None => continue,
+                                };
+                                // Drop the messages which are delivered already by catching up.
+                                let msg = match self.pairings.accept(msg) {
+                                    Some(msg) => msg,
+                                    None => continue,
+                                };
+                                if msg.metadata().is_some() {
+                                    sequence += 1;
+                                }
+
+                                // The last acknowledgement reports zero remaining subscriptions.
+                                drained |= matches!(
+                                    msg,
+                                    Message::Unsubscription { subscriptions: 0, .. }
+                                        | Message::PatternUnsubscription { subscriptions: 0, .. }
+                                );
+
+                                // Catch up on a paired stream once its channel is subscribed to again.
+                                if let Message::Subscription { channel, .. } = &msg {
+                                    match self.pairings.catch_up_from(channel) {
+                                        Some((key, after)) => catch_ups.push(CatchUp::Missed {
+                                            channel: channel.clone(),
+                                            key,
+                                            after,
+                                            attempt: 0,
+                                        }),
+                                        None => {
+                                            if let Some(key) = self.pairings.unstarted(channel) {
+                                                catch_ups.push(CatchUp::Start {
+                                                    channel: channel.clone(),
+                                                    key,
+                                                });
+                                            }
+                                        }
+                                    }
+                                    if catching_up.is_none() {
+                                        catching_up = catch_ups.next().map(|catch_up| Box::pin(self.catch_up(catch_up)) as BoxFuture<'_, _>);
+                                    }
+                                }
+                                yield msg;
+                            }
+                            Err(e @ crate::Error::ServerError(_)) => {
+                                warn!("server replied with an error: {:?}", e);
+                                yield Message::Error(e.clone());
+                                if !self.retry_policy.retries(&e) {
+                                    fatal = Some(e);
+                                    break 'outer;
+                                }
+                            }
                             Err(e) => {
                                 warn!("failed to parse message: {:?}", e);
                                 continue;
@@ -229,22 +1025,100 @@ impl RedisSub {
                     }
                 }
             }
-        }))
+
+            // Give up after an error which will not be resolved by retrying.
+            if let Some(e) = fatal {
+                warn!("giving up after fatal error: {:?}", e);
+                *self.writer.lock().await = None;
+                *self.catch_up_conn.lock().await = None;
+                *self.client_id.lock().unwrap() = None;
+                self.shutdown.send_replace(ShutdownState::Closed);
+                self.listeners.give_up(&e);
+                yield Message::Fatal(e);
+                return;
+            }
+
+            // Close the connection after a shutdown.
+            debug!("closing connection after shutdown");
+            if let Err(e) = self.send_cmd(Command::Quit).await {
+                warn!("failed to send quit command: {:?}", e);
+            }
+            *self.writer.lock().await = None;
+            *self.catch_up_conn.lock().await = None;
+            *self.client_id.lock().unwrap() = None;
+            self.shutdown.send_replace(ShutdownState::Closed);
+            yield Message::Closed;
+        };
+
+        Ok(Box::pin(stream.map(move |msg| {
+            for cache in &self.caches {
+                cache.evict(&msg);
+            }
+            self.routes.dispatch(&msg);
+
+            // Only clone the message if there are receivers.
+            if self.broadcast.receiver_count() > 0 {
+                let _ = self.broadcast.send(msg.clone());
+            }
+            msg
+        })))
     }
 
     /// Send a command to the server.
     async fn send_cmd(&self, command: Command) -> crate::Result<()> {
+        let command = self.namespace.command(command);
         if let Some(writer) = &mut *self.writer.lock().await {
             writer.writable().await?;
 
             debug!("sending command {:?} to redis", &command);
-            writer.write_all(command.to_string().as_bytes()).await?;
+            writer
+                .write_all(&resp::Response::from(&command).to_bytes()?)
+                .await?;
         }
 
         Ok(())
     }
 }
 
+/// Count a new subscription, returns whether it is the first one.
+fn acquire(channels: &mut HashMap<String, usize>, channel: &str) -> bool {
+    let count = channels.entry(channel.to_string()).or_insert(0);
+    *count += 1;
+
+    *count == 1
+}
+
+/// Release a subscription, returns whether it was the last one.
+fn release(channels: &mut HashMap<String, usize>, channel: &str) -> crate::Result<bool> {
+    let count = channels
+        .get_mut(channel)
+        .ok_or(crate::Error::NotSubscribed)?;
+    *count -= 1;
+
+    if *count > 0 {
+        return Ok(false);
+    }
+
+    channels.remove(channel);
+    Ok(true)
+}
+
+/// What woke up the read loop of a connection.
+enum Incoming {
+    /// Data was read from the connection.
+    Read(crate::Result<usize>),
+    /// A catch up on a paired stream has finished.
+    CaughtUp(CatchUp, crate::Result<Vec<StreamEntry>>),
+}
+
+/// Wait for a running future, forever if there is none.
+async fn running<T>(fut: &mut Option<BoxFuture<'_, T>>) -> T {
+    match fut {
+        Some(fut) => fut.await,
+        None => std::future::pending().await,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -307,9 +1181,15 @@ mod tests {
                     msg
                 );
                 match msg {
-                    Message::Message { channel, message } => {
+                    Message::Message {
+                        channel,
+                        message,
+                        metadata,
+                    } => {
                         assert_eq!(channel, "1234".to_string());
                         assert_eq!(message, "1234".to_string());
+                        assert_eq!(metadata.generation, 1);
+                        assert_eq!(metadata.sequence, 0);
                     }
                     _ => unreachable!("already checked this is message"),
                 }
@@ -352,6 +1232,8 @@ mod tests {
             .psubscribe("*420*".to_string())
             .await
             .expect("failed to subscribe to new Redis channel");
+        assert_eq!(redis_sub.matching_patterns("blaze420").await, ["*420*"]);
+        assert!(redis_sub.matching_patterns("blaze").await.is_empty());
         let f = tokio::spawn(async move {
             {
                 let mut stream = redis_sub
@@ -393,6 +1275,7 @@ mod tests {
                         pattern,
                         channel,
                         message,
+                        ..
                     } => {
                         assert_eq!(pattern, "*420*".to_string());
                         assert_eq!(channel, "64209".to_string());
@@ -430,4 +1313,523 @@ mod tests {
             msg
         )
     }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (_client, mut connection, redis_sub) = get_redis_connections().await;
+        let redis_sub = Arc::new(redis_sub);
+
+        redis_sub
+            .subscribe("shutdown".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        redis_sub
+            .psubscribe("shutdown*".to_string())
+            .await
+            .expect("failed to subscribe to new Redis pattern");
+
+        let listener = redis_sub.clone();
+        let f = tokio::spawn(async move {
+            let stream = listener.listen().await.expect("failed to connect to redis");
+            tokio::time::timeout(Duration::from_secs(2), stream.collect::<Vec<_>>())
+                .await
+                .expect("stream did not end after shutdown")
+        });
+
+        // Wait for the subscriptions, then publish a message right before shutting down.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        connection
+            .publish::<&str, &str, u32>("shutdown", "in-flight")
+            .await
+            .expect("failed to send publish command to Redis");
+        redis_sub
+            .shutdown(Duration::from_secs(1))
+            .await
+            .expect("failed to shut down");
+
+        let messages = f.await.expect("background future failed");
+        assert!(
+            messages.iter().any(|msg| msg.is_message()),
+            "in-flight message was not delivered: {:?}",
+            messages
+        );
+        assert!(
+            messages.last().is_some_and(Message::is_closed),
+            "stream did not end with `Closed`: {:?}",
+            messages
+        );
+        assert!(
+            redis_sub.unsubscribe("shutdown".to_string()).await.is_err(),
+            "subscriptions were not forgotten after shutdown"
+        );
+    }
+
+    #[test]
+    fn test_client_name_with_spaces() {
+        assert!(matches!(
+            RedisSub::new("127.0.0.1:6379").with_client_name("my service"),
+            Err(crate::Error::InvalidClientName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_client_name() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let redis_sub = RedisSub::new("127.0.0.1:6379")
+            .with_client_name("subscriber")
+            .expect("client name is valid")
+            .with_initializer(move |conn| {
+                let tx = tx.clone();
+                Box::pin(async move {
+                    let _ = tx.send(conn.command(["CLIENT", "GETNAME"]).await?);
+                    Ok(())
+                })
+            });
+
+        let mut stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+        let msg = tokio::time::timeout(Duration::from_secs(2), stream.next())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message");
+        assert!(
+            matches!(msg, Message::Connected { client_id: Some(_) }),
+            "message after opening stream was not `Connected` with a client ID: {:?}",
+            msg
+        );
+
+        let name = format!("subscriber-{}", std::process::id());
+        assert_eq!(
+            rx.recv().await,
+            Some(resp::Response::Bulk(name.into_bytes()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namespace() {
+        let (_client, mut connection, redis_sub) = get_redis_connections().await;
+        let redis_sub = redis_sub.with_namespace("staging:");
+
+        redis_sub
+            .subscribe("namespaced".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        redis_sub
+            .psubscribe("namespaced.*".to_string())
+            .await
+            .expect("failed to subscribe to new Redis pattern");
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+
+        // Only the channels in the namespace are received.
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            for channel in ["namespaced", "staging:namespaced", "staging:namespaced.1"] {
+                connection
+                    .publish::<&str, &str, u32>(channel, "hello")
+                    .await
+                    .expect("failed to send publish command to Redis");
+            }
+        });
+        let messages =
+            tokio::time::timeout(Duration::from_secs(2), stream.take(5).collect::<Vec<_>>())
+                .await
+                .expect("timeout duration of 2 seconds was exceeded");
+
+        match messages.as_slice() {
+            [Message::Connected { .. }, Message::Subscription { channel, .. }, Message::PatternSubscription {
+                channel: pattern, ..
+            }, Message::Message { channel: first, .. }, Message::PatternMessage {
+                pattern: matched,
+                channel: second,
+                ..
+            }] => {
+                assert_eq!(channel, "namespaced");
+                assert_eq!(pattern, "namespaced.*");
+                assert_eq!(first, "namespaced");
+                assert_eq!(matched, "namespaced.*");
+                assert_eq!(second, "namespaced.1");
+            }
+            _ => panic!("unexpected messages in namespace: {:?}", messages),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gap_after_reconnect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Reply to `CLIENT ID` and the subscription, then close only the first connection.
+        tokio::spawn(async move {
+            let mut first = true;
+            let mut kept = Vec::new();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 64];
+                // Skip the connection checking the server is reachable.
+                if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                    continue;
+                }
+                let _ = socket.write_all(b":7\r\n").await;
+                let _ = socket.read(&mut buf).await;
+                let _ = socket
+                    .write_all(b"*3\r\n$9\r\nsubscribe\r\n$3\r\ngap\r\n:1\r\n")
+                    .await;
+                if first {
+                    first = false;
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+                kept.push(socket);
+            }
+        });
+
+        let redis_sub = RedisSub::new(&addr);
+        redis_sub
+            .subscribe("gap".to_string())
+            .await
+            .expect("failed to subscribe to channel");
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to listener");
+        let messages =
+            tokio::time::timeout(Duration::from_secs(2), stream.take(6).collect::<Vec<_>>())
+                .await
+                .expect("timeout duration of 2 seconds was exceeded");
+
+        match messages.as_slice() {
+            [Message::Connected { .. }, Message::Subscription { .. }, Message::Disconnected(_), Message::Connected { .. }, Message::Gap {
+                channels,
+                patterns,
+                since,
+                until,
+            }, Message::Subscription { .. }] => {
+                assert_eq!(channels, &["gap".to_string()]);
+                assert!(patterns.is_empty());
+                assert!(since <= until);
+            }
+            _ => panic!("unexpected messages after reconnecting: {:?}", messages),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_data_is_not_skipped() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Reply to `CLIENT ID`, then send a malformed integer after the subscription.
+        tokio::spawn(async move {
+            let mut kept = Vec::new();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 64];
+                if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                    continue;
+                }
+                let _ = socket.write_all(b":7\r\n").await;
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(b":abc\r\n").await;
+                kept.push(socket);
+            }
+        });
+
+        let redis_sub = RedisSub::new(&addr).with_overflow_policy(OverflowPolicy::Skip);
+        redis_sub
+            .subscribe("malformed".to_string())
+            .await
+            .expect("failed to subscribe to channel");
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to listener");
+        let messages =
+            tokio::time::timeout(Duration::from_secs(2), stream.take(2).collect::<Vec<_>>())
+                .await
+                .expect("timeout duration of 2 seconds was exceeded");
+
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [
+                    Message::Connected { .. },
+                    Message::Disconnected(crate::Error::ParserError(
+                        crate::ParserError::MalformedResponse
+                    ))
+                ]
+            ),
+            "malformed data did not drop the connection: {:?}",
+            messages
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tracking_invalidation() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Send invalidations on the first connection and close it, then keep the second one.
+        tokio::spawn(async move {
+            let mut client_id = 7;
+            let mut kept = Vec::new();
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 128];
+                // Skip the connection checking the server is reachable.
+                if socket.read(&mut buf).await.unwrap_or(0) == 0 {
+                    continue;
+                }
+                let _ = socket
+                    .write_all(format!(":{}\r\n", client_id).as_bytes())
+                    .await;
+                let _ = socket.read(&mut buf).await;
+                let _ = socket
+                    .write_all(b"*3\r\n$9\r\nsubscribe\r\n$20\r\n__redis__:invalidate\r\n:1\r\n")
+                    .await;
+                client_id += 1;
+                if client_id == 8 {
+                    let _ = socket
+                        .write_all(b"*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*2\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n")
+                        .await;
+                    let _ = socket
+                        .write_all(b"*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*-1\r\n")
+                        .await;
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+                kept.push(socket);
+            }
+        });
+
+        let redis_sub = RedisSub::new(&addr).with_tracking_invalidation();
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to listener");
+        let messages =
+            tokio::time::timeout(Duration::from_secs(2), stream.take(9).collect::<Vec<_>>())
+                .await
+                .expect("timeout duration of 2 seconds was exceeded");
+
+        match messages.as_slice() {
+            [Message::Connected { client_id: Some(7) }, Message::Subscription { .. }, Message::Invalidation {
+                invalidation: Invalidation::Keys(keys),
+                ..
+            }, Message::Invalidation {
+                invalidation: Invalidation::Flush,
+                ..
+            }, Message::Disconnected(_), Message::Connected { client_id: Some(8) }, Message::Gap { .. }, Message::Invalidation {
+                invalidation: Invalidation::Flush,
+                metadata,
+            }, Message::Subscription { .. }] => {
+                assert_eq!(keys, &["user:1".to_string(), "user:2".to_string()]);
+                assert_eq!(metadata.generation, 2);
+            }
+            _ => panic!("unexpected invalidation messages: {:?}", messages),
+        }
+        assert_eq!(redis_sub.client_id(), Some(8));
+    }
+
+    async fn next<S: Stream<Item = Message> + Unpin>(stream: &mut S) -> Message {
+        tokio::time::timeout(Duration::from_secs(2), stream.next())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message")
+    }
+
+    /// Add a message to the paired stream, and publish it with its ID if it is live.
+    async fn add_to_stream(
+        connection: &mut redis::aio::Connection,
+        channel: &str,
+        message: &str,
+        live: bool,
+    ) -> String {
+        let id: String = redis::cmd("XADD")
+            .arg(format!("{}-stream", channel))
+            .arg("*")
+            .arg("message")
+            .arg(message)
+            .query_async(connection)
+            .await
+            .expect("failed to add to stream");
+        if live {
+            connection
+                .publish::<&str, String, u32>(channel, format!("{} {}", id, message))
+                .await
+                .expect("failed to send publish command to Redis");
+        }
+        id
+    }
+
+    #[tokio::test]
+    async fn test_stream_catch_up() {
+        let (_client, mut connection, redis_sub) = get_redis_connections().await;
+        redis_sub
+            .subscribe_with_stream("hybrid".to_string(), "hybrid-stream".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        let mut stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+
+        let client_id = match next(&mut stream).await {
+            Message::Connected { client_id } => client_id.expect("server sent no client ID"),
+            msg => panic!(
+                "message after opening stream was not `Connected`: {:?}",
+                msg
+            ),
+        };
+        assert!(next(&mut stream).await.is_subscription());
+
+        add_to_stream(&mut connection, "hybrid", "live", true).await;
+        match next(&mut stream).await {
+            Message::Message {
+                message, metadata, ..
+            } => {
+                assert_eq!(message, "live");
+                assert!(metadata.stream_id.is_some());
+            }
+            msg => panic!("message was not `Message`: {:?}", msg),
+        }
+
+        // Miss a message while the connection is lost.
+        let missed = add_to_stream(&mut connection, "hybrid", "missed", false).await;
+        redis::cmd("CLIENT")
+            .arg("KILL")
+            .arg("ID")
+            .arg(client_id)
+            .query_async::<_, i64>(&mut connection)
+            .await
+            .expect("failed to kill the connection");
+
+        let mut msg = next(&mut stream).await;
+        while !msg.is_message() {
+            msg = next(&mut stream).await;
+        }
+        match msg {
+            Message::Message {
+                message, metadata, ..
+            } => {
+                assert_eq!(message, "missed");
+                assert_eq!(metadata.stream_id, Some(missed.parse().unwrap()));
+            }
+            _ => unreachable!("already checked this is message"),
+        }
+
+        // A message which is delivered already is dropped.
+        connection
+            .publish::<&str, String, u32>("hybrid", format!("{} missed", missed))
+            .await
+            .expect("failed to send publish command to Redis");
+        add_to_stream(&mut connection, "hybrid", "after", true).await;
+        match next(&mut stream).await {
+            Message::Message { message, .. } => assert_eq!(message, "after"),
+            msg => panic!("message was not `Message`: {:?}", msg),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_catch_up_without_message() {
+        let (_client, mut connection, redis_sub) = get_redis_connections().await;
+        add_to_stream(&mut connection, "quiet", "old", false).await;
+        redis_sub
+            .subscribe_with_stream("quiet".to_string(), "quiet-stream".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        let mut stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+
+        let client_id = match next(&mut stream).await {
+            Message::Connected { client_id } => client_id.expect("server sent no client ID"),
+            msg => panic!(
+                "message after opening stream was not `Connected`: {:?}",
+                msg
+            ),
+        };
+        assert!(next(&mut stream).await.is_subscription());
+        // Let the position in the stream be read, which does not deliver the older entry.
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), stream.next())
+                .await
+                .is_err()
+        );
+
+        // Entries added after subscribing are caught up on, even if no message was delivered.
+        add_to_stream(&mut connection, "quiet", "missed", false).await;
+        redis::cmd("CLIENT")
+            .arg("KILL")
+            .arg("ID")
+            .arg(client_id)
+            .query_async::<_, i64>(&mut connection)
+            .await
+            .expect("failed to kill the connection");
+
+        let mut msg = next(&mut stream).await;
+        while !msg.is_message() {
+            msg = next(&mut stream).await;
+        }
+        match msg {
+            Message::Message { message, .. } => assert_eq!(message, "missed"),
+            _ => unreachable!("already checked this is message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_host_is_fatal() {
+        let redis_sub = RedisSub::new("unknown-host.invalid:6379");
+
+        match redis_sub.listen().await {
+            Err(e) => assert!(e.is_fatal(), "unknown host error was not fatal: {:?}", e),
+            Ok(_) => panic!("connected to an unknown host"),
+        };
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_gives_up() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Reply to `CLIENT ID`, then close the connections.
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0; 64];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(b":7\r\n").await;
+            }
+        });
+
+        let redis_sub = RedisSub::new(&addr).with_retry_policy(|_| false);
+        let stream = redis_sub
+            .listen()
+            .await
+            .expect("failed to connect to listener");
+        let messages = tokio::time::timeout(Duration::from_secs(2), stream.collect::<Vec<_>>())
+            .await
+            .expect("stream did not end after giving up");
+
+        assert!(
+            matches!(
+                messages.as_slice(),
+                [
+                    Message::Connected { client_id: Some(7) },
+                    Message::Disconnected(crate::Error::ZeroBytesRead),
+                    Message::Fatal(crate::Error::ZeroBytesRead)
+                ]
+            ),
+            "unexpected messages before giving up: {:?}",
+            messages
+        );
+    }
 }
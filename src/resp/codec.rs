@@ -0,0 +1,151 @@
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{decode::State, Limits, Response, ResponseRef};
+use crate::message::ParserError;
+
+/// A `tokio_util` codec decoding and encoding RESP values.
+///
+/// Decoding errors end a `FramedRead`, use a [Parser] to skip rejected frames instead.
+///
+/// [Parser]: super::Parser
+#[derive(Debug)]
+pub struct RespCodec {
+    /// Decoding state of the codec.
+    state: State,
+}
+
+impl RespCodec {
+    /// Create a new codec enforcing the limits on decoded data.
+    #[must_use]
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            state: State::new(limits),
+        }
+    }
+}
+
+impl Default for RespCodec {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+impl Decoder for RespCodec {
+    type Item = Response;
+    type Error = crate::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> crate::Result<Option<Response>> {
+        Ok(self.state.next(src)?)
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> crate::Result<Option<Response>> {
+        match self.decode(buf)? {
+            Some(res) => Ok(Some(res)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(ParserError::MalformedResponse.into()),
+        }
+    }
+}
+
+impl Encoder<Response> for RespCodec {
+    type Error = crate::Error;
+
+    fn encode(&mut self, item: Response, dst: &mut BytesMut) -> crate::Result<()> {
+        item.encode(dst)
+    }
+}
+
+impl<'a> Encoder<&'a Response> for RespCodec {
+    type Error = crate::Error;
+
+    fn encode(&mut self, item: &'a Response, dst: &mut BytesMut) -> crate::Result<()> {
+        item.encode(dst)
+    }
+}
+
+impl<'a> Encoder<ResponseRef<'a>> for RespCodec {
+    type Error = crate::Error;
+
+    fn encode(&mut self, item: ResponseRef<'a>, dst: &mut BytesMut) -> crate::Result<()> {
+        item.encode(dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let mut codec = RespCodec::default();
+        let mut buf = BytesMut::from(&b"+OK\r\n:1\r\n$3\r\nfo"[..]);
+
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::SimpleString("OK".to_string()))
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Response::Integer(1)));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"o\r\n");
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Bulk(b"foo".to_vec()))
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_limits() {
+        let limits = Limits {
+            max_bulk_len: 2,
+            ..Limits::default()
+        };
+        let mut codec = RespCodec::new(limits);
+        let mut buf = BytesMut::from(&b"$3\r\nfoo\r\n"[..]);
+
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(crate::Error::ParserError(ParserError::LimitExceeded(_)))
+        ));
+    }
+
+    #[test]
+    fn decode_eof() {
+        let mut codec = RespCodec::default();
+
+        let mut buf = BytesMut::from(&b":1\r\n"[..]);
+        assert_eq!(
+            codec.decode_eof(&mut buf).unwrap(),
+            Some(Response::Integer(1))
+        );
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+
+        // A partial frame at the end of the input is an error.
+        let mut buf = BytesMut::from(&b"*2\r\n:1\r\n"[..]);
+        assert!(matches!(
+            codec.decode_eof(&mut buf),
+            Err(crate::Error::ParserError(ParserError::MalformedResponse))
+        ));
+    }
+
+    #[test]
+    fn encode() {
+        let mut codec = RespCodec::default();
+        let mut buf = BytesMut::new();
+
+        codec.encode(Response::NullArray, &mut buf).unwrap();
+        codec.encode(&Response::Integer(1), &mut buf).unwrap();
+        codec.encode(ResponseRef::Bulk(b"foo"), &mut buf).unwrap();
+        assert_eq!(&buf[..], b"*-1\r\n:1\r\n$3\r\nfoo\r\n");
+
+        assert!(codec
+            .encode(Response::SimpleString("a\r\nb".to_string()), &mut buf)
+            .is_err());
+        assert_eq!(&buf[..], b"*-1\r\n:1\r\n$3\r\nfoo\r\n");
+
+        // Decoding the encoded values gives them back, including the null array.
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Response::NullArray));
+    }
+}
@@ -0,0 +1,564 @@
+use bytes::{Buf, BytesMut};
+use nom::{
+    branch::alt,
+    bytes::streaming::{tag, take},
+    character::streaming::{char, crlf, i64, not_line_ending, u64},
+    combinator::{map, map_res},
+    error::{ErrorKind, FromExternalError, ParseError},
+    multi::count,
+    sequence::{delimited, terminated},
+    IResult,
+};
+
+use super::{Limits, Response, ResponseRef};
+use crate::message::{LimitKind, ParserError};
+
+/// The maximum length of a type and length header line while discarding a frame.
+const MAX_HEADER_LEN: usize = 32;
+
+/// Error type used by the nom parsers, carrying limit violations.
+#[derive(Debug, PartialEq)]
+enum Failure {
+    Nom,
+    Limit(LimitKind),
+}
+
+impl<I> ParseError<I> for Failure {
+    fn from_error_kind(_: I, _: ErrorKind) -> Self {
+        Self::Nom
+    }
+
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I, E> FromExternalError<I, E> for Failure {
+    fn from_external_error(_: I, _: ErrorKind, _: E) -> Self {
+        Self::Nom
+    }
+}
+
+type NomResult<'a, T> = IResult<&'a [u8], T, Failure>;
+
+/// Parse a single value from the start of the input, borrowing its data.
+///
+/// Returns the value together with the amount of bytes it occupied,
+/// or `None` if the input does not contain a complete value yet.
+///
+/// # Errors
+/// Returns an error if the data is malformed or exceeds the limits.
+pub fn parse<'a>(
+    input: &'a [u8],
+    limits: &Limits,
+) -> Result<Option<(ResponseRef<'a>, usize)>, ParserError> {
+    match parse_response(input, limits, 0) {
+        Ok((remainder, response)) => Ok(Some((response, input.len() - remainder.len()))),
+        Err(nom::Err::Incomplete(_)) if input.len() > limits.max_buffered_bytes => {
+            Err(ParserError::LimitExceeded(LimitKind::BufferedBytes))
+        }
+        Err(nom::Err::Incomplete(_)) => Ok(None),
+        Err(nom::Err::Error(Failure::Limit(kind)))
+        | Err(nom::Err::Failure(Failure::Limit(kind))) => Err(ParserError::LimitExceeded(kind)),
+        Err(_) => Err(ParserError::MalformedResponse),
+    }
+}
+
+/// State kept while the remainder of a rejected frame is being discarded.
+#[derive(Debug)]
+struct Discard {
+    /// Amount of elements which have not been seen yet.
+    pending: u64,
+    /// Amount of raw bytes which still have to be dropped.
+    bytes: u64,
+    /// Whether the start of a simple line was already dropped.
+    in_line: bool,
+}
+
+/// Decoding state shared by the [Parser] and the codec.
+#[derive(Debug)]
+pub(super) struct State {
+    /// Limits enforced on the incoming data.
+    limits: Limits,
+    /// Set while a rejected frame is being skipped.
+    discard: Option<Discard>,
+}
+
+impl State {
+    pub(super) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            discard: None,
+        }
+    }
+
+    /// Take the next complete value from the start of the buffer.
+    pub(super) fn next(&mut self, buffer: &mut BytesMut) -> Result<Option<Response>, ParserError> {
+        if self.discard.is_some() && !self.discard(buffer)? {
+            return Ok(None);
+        }
+
+        let (response, consumed) = match parse(buffer, &self.limits)? {
+            Some((response, consumed)) => (response.into_owned(), consumed),
+            None => return Ok(None),
+        };
+
+        buffer.advance(consumed);
+        Ok(Some(response))
+    }
+
+    /// Start skipping the frame at the start of the buffer.
+    pub(super) fn skip(&mut self, buffer: &mut BytesMut) -> Result<(), ParserError> {
+        self.discard = Some(Discard {
+            pending: 1,
+            bytes: 0,
+            in_line: false,
+        });
+        self.discard(buffer)?;
+
+        Ok(())
+    }
+
+    /// Drop buffered data belonging to the skipped frame.
+    ///
+    /// Returns `true` once the complete frame is dropped.
+    fn discard(&mut self, buffer: &mut BytesMut) -> Result<bool, ParserError> {
+        let state = match &mut self.discard {
+            Some(state) => state,
+            None => return Ok(true),
+        };
+
+        loop {
+            if state.pending == 0 && state.bytes == 0 && !state.in_line {
+                self.discard = None;
+                return Ok(true);
+            }
+
+            // Drop the raw bytes of a bulk string.
+            if state.bytes > 0 {
+                let n = std::cmp::min(state.bytes, buffer.len() as u64);
+                buffer.advance(n as usize);
+                state.bytes -= n;
+
+                if state.bytes > 0 {
+                    return Ok(false);
+                }
+                continue;
+            }
+
+            let end = buffer.windows(2).position(|w| w == b"\r\n");
+
+            // Drop the remainder of a simple line.
+            if state.in_line {
+                match end {
+                    Some(pos) => {
+                        buffer.advance(pos + 2);
+                        state.in_line = false;
+                        continue;
+                    }
+                    None => {
+                        // Keep the last byte, it might be the start of the line ending.
+                        buffer.advance(buffer.len().saturating_sub(1));
+                        return Ok(false);
+                    }
+                }
+            }
+
+            let kind = match buffer.first() {
+                Some(kind) => *kind,
+                None => return Ok(false),
+            };
+
+            let pos = match end {
+                Some(pos) => pos,
+                None if matches!(kind, b'+' | b'-' | b':') => {
+                    state.pending -= 1;
+                    state.in_line = true;
+                    continue;
+                }
+                None if buffer.len() > MAX_HEADER_LEN => {
+                    return Err(ParserError::MalformedResponse)
+                }
+                None => return Ok(false),
+            };
+
+            let len = std::str::from_utf8(&buffer[1..pos])
+                .ok()
+                .and_then(|s| s.parse::<i64>().ok());
+            state.pending -= 1;
+            match (kind, len) {
+                (b'+' | b'-' | b':', _) => {}
+                (b'$', Some(len)) if len >= 0 => state.bytes = len as u64 + 2,
+                (b'*', Some(len)) if len >= 0 => {
+                    state.pending = state
+                        .pending
+                        .checked_add(len as u64)
+                        .ok_or(ParserError::MalformedResponse)?;
+                }
+                (b'$' | b'*', Some(-1)) => {}
+                _ => return Err(ParserError::MalformedResponse),
+            }
+            buffer.advance(pos + 2);
+        }
+    }
+}
+
+/// Incremental parser for data received from Redis.
+///
+/// Data is added with [Parser::feed], complete values are taken with [Parser::next_response].
+#[derive(Debug)]
+pub struct Parser {
+    /// Received data which has not been parsed yet.
+    buffer: BytesMut,
+    /// Decoding state of the parser.
+    state: State,
+}
+
+impl Parser {
+    /// Create a new parser with an empty buffer.
+    #[must_use]
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            buffer: BytesMut::new(),
+            state: State::new(limits),
+        }
+    }
+
+    /// Add received data to the buffer.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Parse the next complete value from the buffer.
+    ///
+    /// Returns `None` if more data is needed.
+    ///
+    /// # Errors
+    /// Returns an error if the data is malformed or exceeds the limits.
+    /// The same error is returned until the frame is skipped with [Parser::skip_frame].
+    pub fn next_response(&mut self) -> Result<Option<Response>, ParserError> {
+        self.state.next(&mut self.buffer)
+    }
+
+    /// Skip the frame at the start of the buffer, including data which is not received yet.
+    ///
+    /// # Errors
+    /// Returns an error if the frame structure cannot be followed.
+    pub fn skip_frame(&mut self) -> Result<(), ParserError> {
+        self.state.skip(&mut self.buffer)
+    }
+
+    /// Returns the amount of received bytes which are not parsed yet.
+    #[must_use]
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+fn parse_response<'a>(
+    input: &'a [u8],
+    limits: &Limits,
+    depth: usize,
+) -> NomResult<'a, ResponseRef<'a>> {
+    alt((
+        parse_simple,
+        parse_error,
+        parse_integer,
+        |i| parse_bulk_string(i, limits),
+        parse_null,
+        |i| parse_array(i, limits, depth),
+    ))(input)
+}
+
+fn parse_simple(input: &[u8]) -> NomResult<'_, ResponseRef<'_>> {
+    let (remainder, response) = delimited(
+        char('+'),
+        map_res(not_line_ending, std::str::from_utf8),
+        crlf,
+    )(input)?;
+
+    Ok((remainder, ResponseRef::SimpleString(response)))
+}
+
+fn parse_error(input: &[u8]) -> NomResult<'_, ResponseRef<'_>> {
+    let (remainder, response) = delimited(
+        char('-'),
+        map_res(not_line_ending, std::str::from_utf8),
+        crlf,
+    )(input)?;
+
+    Ok((remainder, ResponseRef::Error(response)))
+}
+
+fn parse_integer(input: &[u8]) -> NomResult<'_, ResponseRef<'_>> {
+    let (remainder, response) = delimited(char(':'), i64, crlf)(input)?;
+
+    Ok((remainder, ResponseRef::Integer(response)))
+}
+
+fn parse_bulk_string<'a>(input: &'a [u8], limits: &Limits) -> NomResult<'a, ResponseRef<'a>> {
+    let (remainder, len) = delimited(char('$'), u64, crlf)(input)?;
+
+    if len > limits.max_bulk_len as u64 {
+        return Err(nom::Err::Failure(Failure::Limit(LimitKind::BulkLength)));
+    }
+
+    let (remainder, data) = terminated(take(len), crlf)(remainder)?;
+
+    Ok((remainder, ResponseRef::Bulk(data)))
+}
+
+fn parse_null(input: &[u8]) -> NomResult<'_, ResponseRef<'_>> {
+    alt((
+        map(terminated(tag("$-1"), crlf), |_| ResponseRef::Null),
+        map(terminated(tag("*-1"), crlf), |_| ResponseRef::NullArray),
+    ))(input)
+}
+
+fn parse_array<'a>(
+    input: &'a [u8],
+    limits: &Limits,
+    depth: usize,
+) -> NomResult<'a, ResponseRef<'a>> {
+    let (remainder, amount) = delimited(char('*'), u64, crlf)(input)?;
+
+    if depth >= limits.max_array_depth {
+        return Err(nom::Err::Failure(Failure::Limit(LimitKind::ArrayDepth)));
+    }
+    if amount > limits.max_array_len as u64 {
+        return Err(nom::Err::Failure(Failure::Limit(LimitKind::ArrayLength)));
+    }
+
+    let (remainder, entries) =
+        count(|i| parse_response(i, limits, depth + 1), amount as usize)(remainder)?;
+
+    Ok((remainder, ResponseRef::Array(entries)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> (&[u8], Response) {
+        let (rem, res) = parse_response(input.as_bytes(), &Limits::default(), 0).unwrap();
+
+        (rem, res.into_owned())
+    }
+
+    #[test]
+    fn simple_string() {
+        let (rem, res) = parse("+OK\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::SimpleString("OK".to_string()), res);
+    }
+
+    #[test]
+    fn error() {
+        let (rem, res) = parse("-Error message\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::Error("Error message".to_string()), res);
+    }
+
+    #[test]
+    fn integer() {
+        let (rem, res) = parse(":1000\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::Integer(1000), res);
+    }
+
+    #[test]
+    fn bulk() {
+        let (rem, res) = parse("$6\r\nfoobar\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::Bulk(b"foobar".to_vec()), res);
+    }
+
+    #[test]
+    fn bulk_binary_safe() {
+        let (rem, res) = parse("$8\r\nfoo\r\nbar\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::Bulk(b"foo\r\nbar".to_vec()), res);
+    }
+
+    #[test]
+    fn null() {
+        let (rem, res) = parse("$-1\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::Null, res);
+    }
+
+    #[test]
+    fn null_array() {
+        let (rem, res) = parse("*-1\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::NullArray, res);
+    }
+
+    #[test]
+    fn array() {
+        let (rem, res) = parse("*0\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(Response::Array(vec![]), res);
+    }
+
+    #[test]
+    fn array_filled() {
+        let (rem, res) = parse("*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(
+            Response::Array(vec![
+                Response::Bulk(b"foo".to_vec()),
+                Response::Bulk(b"bar".to_vec())
+            ]),
+            res
+        );
+    }
+
+    #[test]
+    fn array_nested() {
+        let (rem, res) = parse("*2\r\n*3\r\n:1\r\n:2\r\n:3\r\n*2\r\n+Foo\r\n-Bar\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(
+            Response::Array(vec![
+                Response::Array(vec![
+                    Response::Integer(1),
+                    Response::Integer(2),
+                    Response::Integer(3),
+                ]),
+                Response::Array(vec![
+                    Response::SimpleString("Foo".to_string()),
+                    Response::Error("Bar".to_string())
+                ])
+            ]),
+            res
+        );
+    }
+
+    #[test]
+    fn array_null() {
+        let (rem, res) = parse("*3\r\n$3\r\nfoo\r\n$-1\r\n$3\r\nbar\r\n");
+
+        assert!(rem.is_empty());
+        assert_eq!(
+            Response::Array(vec![
+                Response::Bulk(b"foo".to_vec()),
+                Response::Null,
+                Response::Bulk(b"bar".to_vec())
+            ]),
+            res
+        );
+    }
+
+    #[test]
+    fn borrowed() {
+        let input = b"*2\r\n$3\r\nfoo\r\n+OK\r\n:1\r\n";
+        let (res, consumed) = super::parse(input, &Limits::default()).unwrap().unwrap();
+
+        assert_eq!(input.len() - 4, consumed);
+        assert_eq!(
+            ResponseRef::Array(vec![
+                ResponseRef::Bulk(b"foo"),
+                ResponseRef::SimpleString("OK")
+            ]),
+            res
+        );
+        assert_eq!(Ok(None), super::parse(b"*2\r\n:1\r\n", &Limits::default()));
+    }
+
+    #[test]
+    fn parser_split_input() {
+        let mut parser = Parser::new(Limits::default());
+
+        parser.feed(b"*2\r\n$3\r\nfo");
+        assert_eq!(Ok(None), parser.next_response());
+        parser.feed(b"o\r\n:1\r\n+OK\r\n");
+        assert_eq!(
+            Ok(Some(Response::Array(vec![
+                Response::Bulk(b"foo".to_vec()),
+                Response::Integer(1)
+            ]))),
+            parser.next_response()
+        );
+        assert_eq!(
+            Ok(Some(Response::SimpleString("OK".to_string()))),
+            parser.next_response()
+        );
+        assert_eq!(Ok(None), parser.next_response());
+    }
+
+    #[test]
+    fn limit_bulk_len() {
+        let limits = Limits {
+            max_bulk_len: 4,
+            ..Limits::default()
+        };
+        let mut parser = Parser::new(limits);
+
+        parser.feed(b"*2\r\n$5\r\nhel");
+        assert_eq!(
+            Err(ParserError::LimitExceeded(LimitKind::BulkLength)),
+            parser.next_response()
+        );
+
+        parser.skip_frame().unwrap();
+        parser.feed(b"lo\r\n$2\r\nok\r\n:1\r\n");
+        assert_eq!(Ok(Some(Response::Integer(1))), parser.next_response());
+    }
+
+    #[test]
+    fn limit_array() {
+        let limits = Limits {
+            max_array_len: 2,
+            max_array_depth: 2,
+            ..Limits::default()
+        };
+        let mut parser = Parser::new(limits);
+
+        parser.feed(b"*3\r\n:1\r\n:2\r\n:3\r\n");
+        assert_eq!(
+            Err(ParserError::LimitExceeded(LimitKind::ArrayLength)),
+            parser.next_response()
+        );
+        parser.skip_frame().unwrap();
+
+        parser.feed(b"*1\r\n*1\r\n*1\r\n+deep\r\n:4\r\n");
+        assert_eq!(
+            Err(ParserError::LimitExceeded(LimitKind::ArrayDepth)),
+            parser.next_response()
+        );
+        parser.skip_frame().unwrap();
+
+        assert_eq!(Ok(Some(Response::Integer(4))), parser.next_response());
+    }
+
+    #[test]
+    fn limit_buffered_bytes() {
+        let limits = Limits {
+            max_buffered_bytes: 8,
+            ..Limits::default()
+        };
+        let mut parser = Parser::new(limits);
+
+        parser.feed(b"+a very long line");
+        assert_eq!(
+            Err(ParserError::LimitExceeded(LimitKind::BufferedBytes)),
+            parser.next_response()
+        );
+
+        parser.skip_frame().unwrap();
+        parser.feed(b" which goes on\r");
+        assert_eq!(Ok(None), parser.next_response());
+        parser.feed(b"\n:5\r\n");
+        assert_eq!(Ok(Some(Response::Integer(5))), parser.next_response());
+    }
+}
@@ -0,0 +1,151 @@
+use bytes::BufMut;
+
+use super::{Response, ResponseRef};
+
+impl ResponseRef<'_> {
+    /// Serialize the value as RESP into the buffer.
+    ///
+    /// # Errors
+    /// Returns an error if a simple string or error contains `\r` or `\n`,
+    /// which would end its line early. Nothing is written then.
+    pub fn encode<B: BufMut>(&self, buf: &mut B) -> crate::Result<()> {
+        self.check()?;
+        self.write(buf);
+        Ok(())
+    }
+
+    /// Serialize the value as RESP into a new buffer.
+    ///
+    /// # Errors
+    /// Returns an error if a simple string or error contains `\r` or `\n`.
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.encode(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Make sure the lines of simple strings and errors do not contain line breaks.
+    fn check(&self) -> crate::Result<()> {
+        match self {
+            Self::SimpleString(line) | Self::Error(line) if line.contains(['\r', '\n']) => {
+                Err(crate::Error::InvalidLine(line.to_string()))
+            }
+            Self::Array(a) => a.iter().try_for_each(Self::check),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the value, which is checked already.
+    fn write<B: BufMut>(&self, buf: &mut B) {
+        match self {
+            Self::Null => buf.put_slice(b"$-1\r\n"),
+            Self::NullArray => buf.put_slice(b"*-1\r\n"),
+            Self::SimpleString(s) => write_line(buf, b'+', s.as_bytes()),
+            Self::Error(e) => write_line(buf, b'-', e.as_bytes()),
+            Self::Integer(i) => write_line(buf, b':', i.to_string().as_bytes()),
+            Self::Bulk(b) => {
+                write_line(buf, b'$', b.len().to_string().as_bytes());
+                buf.put_slice(b);
+                buf.put_slice(b"\r\n");
+            }
+            Self::Array(a) => {
+                write_line(buf, b'*', a.len().to_string().as_bytes());
+                for res in a {
+                    res.write(buf);
+                }
+            }
+        }
+    }
+}
+
+impl Response {
+    /// Serialize the value as RESP into the buffer.
+    ///
+    /// # Errors
+    /// Returns an error if a simple string or error contains `\r` or `\n`,
+    /// which would end its line early. Nothing is written then.
+    pub fn encode<B: BufMut>(&self, buf: &mut B) -> crate::Result<()> {
+        self.to_ref().encode(buf)
+    }
+
+    /// Serialize the value as RESP into a new buffer.
+    ///
+    /// # Errors
+    /// Returns an error if a simple string or error contains `\r` or `\n`.
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        self.to_ref().to_bytes()
+    }
+}
+
+/// Write a type byte followed by a line of data.
+fn write_line<B: BufMut>(buf: &mut B, kind: u8, data: &[u8]) {
+    buf.put_u8(kind);
+    buf.put_slice(data);
+    buf.put_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resp::{parse, Limits};
+
+    #[test]
+    fn encode_values() {
+        assert_eq!(b"$-1\r\n".to_vec(), Response::Null.to_bytes().unwrap());
+        assert_eq!(b"*-1\r\n".to_vec(), Response::NullArray.to_bytes().unwrap());
+        assert_eq!(
+            b"+OK\r\n".to_vec(),
+            Response::SimpleString("OK".to_string()).to_bytes().unwrap()
+        );
+        assert_eq!(
+            b"-ERR bad\r\n".to_vec(),
+            Response::Error("ERR bad".to_string()).to_bytes().unwrap()
+        );
+        assert_eq!(
+            b":-12\r\n".to_vec(),
+            Response::Integer(-12).to_bytes().unwrap()
+        );
+        assert_eq!(
+            b"$6\r\nfoobar\r\n".to_vec(),
+            Response::Bulk(b"foobar".to_vec()).to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn encode_line_breaks() {
+        let injected = Response::Array(vec![
+            Response::Integer(1),
+            Response::SimpleString("OK\r\n+INJECTED".to_string()),
+        ]);
+        let mut buf = Vec::new();
+        assert!(matches!(
+            injected.encode(&mut buf),
+            Err(crate::Error::InvalidLine(_))
+        ));
+        assert!(buf.is_empty());
+        assert!(Response::Error("ERR\n".to_string()).to_bytes().is_err());
+    }
+
+    #[test]
+    fn encode_command() {
+        assert_eq!(
+            b"*2\r\n$9\r\nSUBSCRIBE\r\n$3\r\na b\r\n".to_vec(),
+            Response::command(["SUBSCRIBE", "a b"]).to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let res = Response::Array(vec![
+            Response::Bulk(b"foo\r\nbar".to_vec()),
+            Response::Null,
+            Response::NullArray,
+            Response::Array(vec![Response::Integer(1), Response::Error("e".to_string())]),
+        ]);
+        let bytes = res.to_bytes().unwrap();
+        let (parsed, consumed) = parse(&bytes, &Limits::default()).unwrap().unwrap();
+
+        assert_eq!(bytes.len(), consumed);
+        assert_eq!(res, parsed.into_owned());
+    }
+}
@@ -0,0 +1,129 @@
+//! Encoding and decoding of the Redis serialization protocol (RESP2).
+//!
+//! This is the protocol layer used by [RedisSub], usable on its own for proxies or test servers.
+//! Values are represented by [Response] and its borrowed form [ResponseRef],
+//! incoming data is decoded by a [Parser] or the [RespCodec] for use with `tokio_util`.
+//!
+//! [RedisSub]: crate::RedisSub
+
+mod codec;
+mod decode;
+mod encode;
+
+pub use codec::RespCodec;
+pub use decode::{parse, Parser};
+
+/// A single owned RESP value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// A null bulk string.
+    Null,
+    /// A null array.
+    NullArray,
+    /// A simple string, such as `OK`.
+    SimpleString(String),
+    /// An error reply.
+    Error(String),
+    /// A signed 64 bit integer.
+    Integer(i64),
+    /// A binary safe bulk string.
+    Bulk(Vec<u8>),
+    /// An array of values.
+    Array(Vec<Response>),
+}
+
+/// A single RESP value borrowing its data from the input buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseRef<'a> {
+    /// A null bulk string.
+    Null,
+    /// A null array.
+    NullArray,
+    /// A simple string, such as `OK`.
+    SimpleString(&'a str),
+    /// An error reply.
+    Error(&'a str),
+    /// A signed 64 bit integer.
+    Integer(i64),
+    /// A binary safe bulk string.
+    Bulk(&'a [u8]),
+    /// An array of values.
+    Array(Vec<ResponseRef<'a>>),
+}
+
+impl Response {
+    /// Create a command as sent by clients: an array of bulk strings.
+    pub fn command<I, A>(args: I) -> Self
+    where
+        I: IntoIterator<Item = A>,
+        A: AsRef<[u8]>,
+    {
+        Self::Array(
+            args.into_iter()
+                .map(|arg| Self::Bulk(arg.as_ref().to_vec()))
+                .collect(),
+        )
+    }
+
+    /// Borrow this value as a [ResponseRef].
+    #[must_use]
+    pub fn to_ref(&self) -> ResponseRef<'_> {
+        match self {
+            Self::Null => ResponseRef::Null,
+            Self::NullArray => ResponseRef::NullArray,
+            Self::SimpleString(s) => ResponseRef::SimpleString(s),
+            Self::Error(e) => ResponseRef::Error(e),
+            Self::Integer(i) => ResponseRef::Integer(*i),
+            Self::Bulk(b) => ResponseRef::Bulk(b),
+            Self::Array(a) => ResponseRef::Array(a.iter().map(Self::to_ref).collect()),
+        }
+    }
+}
+
+impl ResponseRef<'_> {
+    /// Copy the borrowed data into an owned [Response].
+    #[must_use]
+    pub fn into_owned(self) -> Response {
+        match self {
+            Self::Null => Response::Null,
+            Self::NullArray => Response::NullArray,
+            Self::SimpleString(s) => Response::SimpleString(s.to_string()),
+            Self::Error(e) => Response::Error(e.to_string()),
+            Self::Integer(i) => Response::Integer(i),
+            Self::Bulk(b) => Response::Bulk(b.to_vec()),
+            Self::Array(a) => Response::Array(a.into_iter().map(Self::into_owned).collect()),
+        }
+    }
+}
+
+impl<'a> From<ResponseRef<'a>> for Response {
+    fn from(res: ResponseRef<'a>) -> Self {
+        res.into_owned()
+    }
+}
+
+/// Limits enforced by the parser on incoming data.
+///
+/// The defaults mirror the `proto-max-bulk-len` default of the Redis server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum length of a single bulk string, in bytes.
+    pub max_bulk_len: usize,
+    /// Maximum amount of elements in a single array.
+    pub max_array_len: usize,
+    /// Maximum nesting depth of arrays.
+    pub max_array_depth: usize,
+    /// Maximum amount of unparsed bytes kept in the input buffer.
+    pub max_buffered_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_len: 1024 * 1024,
+            max_array_depth: 16,
+            max_buffered_bytes: 513 * 1024 * 1024,
+        }
+    }
+}
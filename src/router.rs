@@ -0,0 +1,390 @@
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::sync::Arc;
+
+use thiserror::Error;
+use tokio::sync::Semaphore;
+use tokio_stream::StreamExt;
+
+use crate::subscription::Target;
+use crate::{BoxFuture, Message, RedisSub};
+
+/// Result returned by the handlers of a [Router].
+pub type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// A handler of a [Router], called with every message of its channel or pattern.
+type Handler = dyn Fn(Message) -> BoxFuture<'static, HandlerResult> + Send + Sync;
+
+/// Called with the channel and the error when a handler fails.
+type ErrorHandler = dyn Fn(&str, &HandlerError) + Send + Sync;
+
+/// Default amount of handlers running at the same time.
+const DEFAULT_CONCURRENCY: usize = 16;
+
+/// Maximum amount of handlers running at the same time, which can all be waited for at once.
+const MAX_CONCURRENCY: usize = if Semaphore::MAX_PERMITS < u32::MAX as usize {
+    Semaphore::MAX_PERMITS
+} else {
+    u32::MAX as usize
+};
+
+/// How a handler of a [Router] failed.
+#[derive(Error, Debug)]
+pub enum HandlerError {
+    #[error("The handler returned an error: {0}")]
+    Failed(Box<dyn std::error::Error + Send + Sync>),
+    #[error("The handler panicked: {0}")]
+    Panicked(String),
+}
+
+/// Dispatches messages to async handlers by their channel or pattern.
+///
+/// The channels and patterns of the handlers are subscribed to when the router runs,
+/// and released again once it stops.
+/// A message is passed to every handler of its channel, or of the pattern it matched,
+/// and up to the concurrency limit of handlers run at the same time.
+/// Handlers which return an error or panic are reported to the error handler,
+/// without stopping the router.
+pub struct Router {
+    /// The subscription object driven by the router.
+    sub: Arc<RedisSub>,
+    /// The handlers, with the channel or pattern they handle.
+    routes: Vec<(Target, Arc<Handler>)>,
+    /// Maximum amount of handlers running at the same time.
+    concurrency: usize,
+    /// Called when a handler fails.
+    on_error: Arc<ErrorHandler>,
+}
+
+impl Router {
+    /// Create a router without handlers.
+    #[must_use]
+    pub fn new(sub: Arc<RedisSub>) -> Self {
+        Self {
+            sub,
+            routes: Vec::new(),
+            concurrency: DEFAULT_CONCURRENCY,
+            on_error: Arc::new(|channel, e| {
+                warn!("handler of channel {} failed: {}", channel, e);
+            }),
+        }
+    }
+
+    /// Handle the messages of a channel.
+    #[must_use]
+    pub fn route<F, Fut>(self, channel: &str, handler: F) -> Self
+    where
+        F: Fn(Message) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.add(Target::Channel(channel.to_string()), handler)
+    }
+
+    /// Handle the messages matching a pattern of channels, see [glob].
+    ///
+    /// [glob]: crate::glob
+    #[must_use]
+    pub fn route_pattern<F, Fut>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(Message) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.add(Target::Pattern(pattern.to_string()), handler)
+    }
+
+    /// Set the maximum amount of handlers running at the same time.
+    ///
+    /// With a concurrency of one, messages are handled one by one in the order they are received.
+    ///
+    /// # Panics
+    /// Panics if the concurrency is zero, or larger than [u32::MAX] or [Semaphore::MAX_PERMITS].
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "the concurrency must be at least one");
+        assert!(
+            concurrency <= MAX_CONCURRENCY,
+            "the concurrency must be at most {}",
+            MAX_CONCURRENCY
+        );
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Set the function called with the channel and the error when a handler fails.
+    ///
+    /// By default the error is logged.
+    #[must_use]
+    pub fn with_error_handler<F>(mut self, on_error: F) -> Self
+    where
+        F: Fn(&str, &HandlerError) + Send + Sync + 'static,
+    {
+        self.on_error = Arc::new(on_error);
+        self
+    }
+
+    fn add<F, Fut>(mut self, target: Target, handler: F) -> Self
+    where
+        F: Fn(Message) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.routes
+            .push((target, Arc::new(move |msg| Box::pin(handler(msg)))));
+        self
+    }
+
+    /// The handlers of a message.
+    fn handlers<'a>(&'a self, msg: &Message) -> impl Iterator<Item = &'a Arc<Handler>> + 'a {
+        let target = match msg {
+            Message::Message { channel, .. } => Some(Target::Channel(channel.clone())),
+            Message::PatternMessage { pattern, .. }
+            | Message::Binary {
+                pattern: Some(pattern),
+                ..
+            } => Some(Target::Pattern(pattern.clone())),
+            Message::Binary { channel, .. } => Some(Target::Channel(channel.clone())),
+            _ => None,
+        };
+
+        self.routes
+            .iter()
+            .filter(move |(route, _)| target.as_ref() == Some(route))
+            .map(|(_, handler)| handler)
+    }
+
+    /// Subscribe to the routes, and dispatch messages until the stream ends.
+    ///
+    /// Waits for the running handlers, and releases the subscriptions of the routes before returning.
+    ///
+    /// # Errors
+    /// Returns an error if the first connection attempt fails, or the stream ends with a [Message::Fatal].
+    pub async fn run(&self) -> crate::Result<()> {
+        for (subscribed, (target, _)) in self.routes.iter().enumerate() {
+            let res = match target {
+                Target::Channel(channel) => self.sub.subscribe(channel.clone()).await,
+                Target::Pattern(pattern) => self.sub.psubscribe(pattern.clone()).await,
+            };
+            // The failed subscription is counted as well.
+            if let Err(e) = res {
+                self.release(&self.routes[..=subscribed]).await;
+                return Err(e);
+            }
+        }
+
+        let res = self.dispatch().await;
+        self.release(&self.routes).await;
+        res
+    }
+
+    /// Dispatch messages until the stream ends, then wait for the running handlers.
+    async fn dispatch(&self) -> crate::Result<()> {
+        let permits = Arc::new(Semaphore::new(self.concurrency));
+        let mut stream = self.sub.listen().await?;
+        let mut res = Ok(());
+
+        while let Some(msg) = stream.next().await {
+            let channel = match &msg {
+                Message::Message { channel, .. }
+                | Message::PatternMessage { channel, .. }
+                | Message::Binary { channel, .. } => channel.clone(),
+                Message::Fatal(e) => {
+                    res = Err(e.clone());
+                    break;
+                }
+                _ => continue,
+            };
+
+            for handler in self.handlers(&msg) {
+                // Wait for a running handler to finish when at the limit.
+                let permit = match permits.clone().acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => unreachable!("the semaphore is never closed"),
+                };
+                let handler = tokio::spawn(handler(msg.clone()));
+                let on_error = self.on_error.clone();
+                let channel = channel.clone();
+
+                // Report the result from a separate task, which also catches panics.
+                tokio::spawn(async move {
+                    let e = match handler.await {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(HandlerError::Failed(e)),
+                        Err(e) if e.is_panic() => {
+                            Some(HandlerError::Panicked(panic_message(e.into_panic())))
+                        }
+                        // Handlers are only cancelled when the runtime shuts down, which is no failure.
+                        Err(_) => None,
+                    };
+                    if let Some(e) = e {
+                        on_error(&channel, &e);
+                    }
+                    drop(permit);
+                });
+            }
+        }
+
+        // Wait for the running handlers, the concurrency is at most `u32::MAX`.
+        let _ = permits.acquire_many(self.concurrency as u32).await;
+        res
+    }
+
+    /// Release the subscriptions of routes.
+    async fn release(&self, routes: &[(Target, Arc<Handler>)]) {
+        for (target, _) in routes {
+            self.sub.release(target.clone()).await;
+        }
+    }
+}
+
+impl Debug for Router {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Router")
+            .field("sub", &self.sub)
+            .field(
+                "routes",
+                &self
+                    .routes
+                    .iter()
+                    .map(|(target, _)| target)
+                    .collect::<Vec<_>>(),
+            )
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
+
+/// The message of a panic, if it is a string.
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    #[test]
+    #[should_panic(expected = "the concurrency must be at most")]
+    fn test_concurrency_limit() {
+        let _ = Router::new(Arc::new(RedisSub::new("127.0.0.1:6379")))
+            .with_concurrency(MAX_CONCURRENCY + 1);
+    }
+
+    #[tokio::test]
+    async fn test_releases_routes() {
+        // Nothing listens on a port which was just released.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let sub = Arc::new(RedisSub::new(&addr));
+        let res = Router::new(sub.clone())
+            .route("router", |_| async { Ok(()) })
+            .route_pattern("router.*", |_| async { Ok(()) })
+            .run()
+            .await;
+        assert!(res.is_err(), "router ran without a server");
+
+        assert!(matches!(
+            sub.unsubscribe("router".to_string()).await,
+            Err(crate::Error::NotSubscribed)
+        ));
+        assert!(sub.matching_patterns("router.a").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (error_tx, mut error_rx) = mpsc::unbounded_channel();
+        let sub = Arc::new(RedisSub::new("127.0.0.1:6379"));
+        let router = Router::new(sub.clone())
+            .route("router", {
+                let tx = tx.clone();
+                move |msg| {
+                    let tx = tx.clone();
+                    async move {
+                        if let Message::Message { message, .. } = msg {
+                            let _ = tx.send(format!("channel {}", message));
+                        }
+                        Ok(())
+                    }
+                }
+            })
+            .route_pattern("router.*", move |msg| {
+                let tx = tx.clone();
+                async move {
+                    match msg {
+                        Message::PatternMessage { message, .. } if message == "fail" => {
+                            Err("failed".into())
+                        }
+                        Message::PatternMessage { message, .. } if message == "panic" => {
+                            panic!("panicked")
+                        }
+                        Message::PatternMessage { message, .. } => {
+                            let _ = tx.send(format!("pattern {}", message));
+                            Ok(())
+                        }
+                        _ => Ok(()),
+                    }
+                }
+            })
+            .with_concurrency(1)
+            .with_error_handler(move |channel, e| {
+                let _ = error_tx.send((channel.to_string(), e.to_string()));
+            });
+        let running = tokio::spawn(async move { router.run().await });
+
+        // Wait for the subscriptions.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        for (channel, message) in [
+            ("router", "1"),
+            ("router.a", "fail"),
+            ("router.b", "panic"),
+            ("router.c", "2"),
+        ] {
+            connection
+                .publish::<&str, &str, u32>(channel, message)
+                .await
+                .expect("failed to send publish command to Redis");
+        }
+
+        for expected in ["channel 1", "pattern 2"] {
+            let handled = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("timeout duration of 2 seconds was exceeded");
+            assert_eq!(handled.as_deref(), Some(expected));
+        }
+
+        let mut errors = Vec::new();
+        for _ in 0..2 {
+            errors.push(error_rx.recv().await.expect("expected an error"));
+        }
+        assert_eq!(errors[0].0, "router.a");
+        assert!(errors[0].1.contains("failed"));
+        assert_eq!(errors[1].0, "router.b");
+        assert!(errors[1].1.contains("panicked"));
+
+        sub.shutdown(Duration::from_secs(1))
+            .await
+            .expect("failed to shut down");
+        tokio::time::timeout(Duration::from_secs(2), running)
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("router task panicked")
+            .expect("router returned an error");
+    }
+}
@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use async_stream::stream;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Instant};
+use tokio_stream::Stream;
+
+use crate::connection::{connect, connect_with_backoff, BoxFuture, Initializers, RetryPolicy};
+use crate::entry::StreamRead;
+use crate::resp::Response;
+use crate::{Connection, Limits, Message, Metadata, ParserError, StreamEntry, StreamId};
+
+/// Default time `XREADGROUP` waits for new entries.
+const DEFAULT_BLOCK: Duration = Duration::from_secs(5);
+
+/// Default amount of entries read from every stream at once.
+const DEFAULT_COUNT: usize = 128;
+
+/// Redis Streams consumer in a consumer group.
+///
+/// This reads new entries with `XREADGROUP` from all its streams,
+/// yielding them as [Message::Entry] from the stream returned by `.listen()`.
+/// Entries stay pending until they are acknowledged with `.ack()`.
+#[derive(Debug)]
+pub struct StreamSub {
+    /// Address of the redis server.
+    addr: String,
+    /// Name of the consumer group.
+    group: String,
+    /// Name of this consumer within the group.
+    consumer: String,
+    /// Keys of the streams to read from.
+    streams: Vec<String>,
+    /// Time `XREADGROUP` waits for new entries.
+    block: Duration,
+    /// Amount of entries read from every stream at once.
+    count: usize,
+    /// Minimum idle time of the pending entries claimed from other consumers.
+    claim_idle: Option<Duration>,
+    /// Limits enforced on the incoming data.
+    limits: Limits,
+    /// Decides whether to keep retrying after an error.
+    retry_policy: RetryPolicy,
+    /// Functions run on every new connection.
+    initializers: Initializers,
+    /// Connection for acknowledgements, as the reading connection is blocked.
+    ack_conn: Mutex<Option<Connection>>,
+    /// Amount of connections made, numbering the connections.
+    generation: AtomicU64,
+}
+
+impl StreamSub {
+    /// Create the new stream consumer.
+    /// This does not connect to the server, use `.listen()` for that.
+    #[must_use]
+    pub fn new(addr: &str, group: &str, consumer: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            streams: Vec::new(),
+            block: DEFAULT_BLOCK,
+            count: DEFAULT_COUNT,
+            claim_idle: None,
+            limits: Limits::default(),
+            retry_policy: RetryPolicy::default(),
+            initializers: Initializers::default(),
+            ack_conn: Mutex::new(None),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Add a stream to read from.
+    ///
+    /// The consumer group is created on the stream when it does not exist,
+    /// starting at the entries added after it is created.
+    /// The stream itself is created when it does not exist either.
+    #[must_use]
+    pub fn with_stream(mut self, key: &str) -> Self {
+        self.streams.push(key.to_string());
+        self
+    }
+
+    /// Set the time to wait for new entries in a single read.
+    #[must_use]
+    pub fn with_block(mut self, block: Duration) -> Self {
+        self.block = block;
+        self
+    }
+
+    /// Set the amount of entries read from every stream at once.
+    #[must_use]
+    pub fn with_count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Claim the entries which are pending for other consumers for at least `min_idle`,
+    /// for example because the consumer crashed.
+    ///
+    /// Claiming is done with `XAUTOCLAIM` before reading, at most once every `min_idle`.
+    #[must_use]
+    pub fn with_autoclaim(mut self, min_idle: Duration) -> Self {
+        self.claim_idle = Some(min_idle);
+        self
+    }
+
+    /// Set the limits enforced on data received from the server.
+    #[must_use]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the function deciding whether to keep retrying after an error.
+    ///
+    /// See [RedisSub::with_retry_policy].
+    ///
+    /// [RedisSub::with_retry_policy]: crate::RedisSub::with_retry_policy
+    #[must_use]
+    pub fn with_retry_policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&crate::Error) -> bool + Send + Sync + 'static,
+    {
+        self.retry_policy = RetryPolicy::new(policy);
+        self
+    }
+
+    /// Add a function which is run on every new connection.
+    ///
+    /// See [RedisSub::with_initializer].
+    ///
+    /// [RedisSub::with_initializer]: crate::RedisSub::with_initializer
+    #[must_use]
+    pub fn with_initializer<F>(mut self, initializer: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut Connection) -> BoxFuture<'a, crate::Result<()>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.initializers.push(Box::new(initializer));
+        self
+    }
+
+    /// Acknowledge entries of a stream, so they are no longer pending.
+    ///
+    /// Returns the amount of entries which were pending.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream,
+    /// or if the server replied with an error.
+    pub async fn ack(&self, stream: &str, ids: &[StreamId]) -> crate::Result<i64> {
+        let mut ack_conn = self.ack_conn.lock().await;
+        let conn = match &mut *ack_conn {
+            Some(conn) => conn,
+            None => {
+                let (read, write) = connect(&self.addr).await?.into_split();
+                let mut conn = Connection::new(read, write, self.limits);
+                self.initializers.run(&mut conn).await?;
+                ack_conn.insert(conn)
+            }
+        };
+
+        let args = ["XACK", stream, &self.group]
+            .into_iter()
+            .map(str::to_string)
+            .chain(ids.iter().map(ToString::to_string));
+        match conn.command(args).await {
+            Ok(Response::Integer(n)) => Ok(n),
+            Ok(_) => Err(ParserError::MalformedResponse.into()),
+            Err(e @ crate::Error::ServerError(_)) => Err(e),
+            Err(e) => {
+                // Reconnect on the next acknowledgement.
+                *ack_conn = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Connect to the Redis server specified by `self.addr`.
+    ///
+    /// Handles exponential backoff.
+    async fn connect(&self, fail_fast: bool) -> crate::Result<Connection> {
+        let (read, write) = connect_with_backoff(&self.addr, fail_fast, &self.retry_policy, |_| {})
+            .await?
+            .into_split();
+
+        Ok(Connection::new(read, write, self.limits))
+    }
+
+    /// Prepare a new connection, creating the consumer groups which do not exist.
+    ///
+    /// Returns the client ID, which is `None` if the server does not support `CLIENT ID`.
+    async fn initialize(&self, conn: &mut Connection) -> crate::Result<Option<i64>> {
+        let client_id = conn.client_id().await?;
+        self.initializers.run(conn).await?;
+
+        for stream in &self.streams {
+            let res = conn
+                .command(["XGROUP", "CREATE", stream, &self.group, "$", "MKSTREAM"])
+                .await;
+            match res {
+                Ok(_) => debug!("created consumer group {} on {}", self.group, stream),
+                Err(crate::Error::ServerError(e)) if e.starts_with("BUSYGROUP") => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(client_id)
+    }
+
+    /// Read the entries of all streams, waiting for them at most the block time.
+    ///
+    /// The streams in `history` are read from the entries pending for this consumer
+    /// after the given ID, the others from the new entries.
+    /// Streams are removed from `history` once all their pending entries are read.
+    /// Pending entries which were deleted from the stream are acknowledged, and not returned.
+    async fn read(
+        &self,
+        conn: &mut Connection,
+        history: &mut HashMap<String, StreamId>,
+    ) -> crate::Result<Vec<(String, StreamEntry)>> {
+        let count = self.count.to_string();
+        let block = self.block.as_millis().to_string();
+        let args = [
+            "XREADGROUP",
+            "GROUP",
+            &self.group,
+            &self.consumer,
+            "COUNT",
+            &count,
+            "BLOCK",
+            &block,
+            "STREAMS",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .chain(self.streams.iter().cloned())
+        .chain(self.streams.iter().map(|stream| {
+            history
+                .get(stream)
+                .map_or_else(|| ">".to_string(), ToString::to_string)
+        }));
+
+        let streams = StreamEntry::from_read(conn.command(args).await?)?;
+        history.retain(|stream, last_id| {
+            let last = streams
+                .iter()
+                .find(|read| &read.key == stream)
+                .and_then(StreamRead::last_id);
+            match last {
+                Some(id) => {
+                    *last_id = id;
+                    true
+                }
+                None => false,
+            }
+        });
+
+        // Pending entries which were deleted cannot be processed, so they are acknowledged.
+        for read in &streams {
+            if !read.deleted.is_empty() {
+                debug!(
+                    "acknowledging {} deleted entries of {}",
+                    read.deleted.len(),
+                    read.key
+                );
+                self.ack(&read.key, &read.deleted).await?;
+            }
+        }
+
+        Ok(streams
+            .into_iter()
+            .flat_map(|read| {
+                let key = read.key;
+                read.entries.into_iter().map(move |e| (key.clone(), e))
+            })
+            .collect())
+    }
+
+    /// Claim the entries which are idle for too long, continuing from the cursor of every stream.
+    async fn claim(
+        &self,
+        conn: &mut Connection,
+        min_idle: Duration,
+        cursors: &mut HashMap<String, StreamId>,
+    ) -> crate::Result<Vec<(String, StreamEntry)>> {
+        let count = self.count.to_string();
+        let min_idle = min_idle.as_millis().to_string();
+        let mut claimed = Vec::new();
+
+        for stream in &self.streams {
+            let start = cursors.get(stream).copied().unwrap_or_default().to_string();
+            let res = conn
+                .command([
+                    "XAUTOCLAIM",
+                    stream,
+                    &self.group,
+                    &self.consumer,
+                    &min_idle,
+                    &start,
+                    "COUNT",
+                    &count,
+                ])
+                .await?;
+
+            let (cursor, entries) = StreamEntry::from_claimed(res)?;
+            cursors.insert(stream.clone(), cursor);
+            claimed.extend(entries.into_iter().map(|e| (stream.clone(), e)));
+        }
+
+        Ok(claimed)
+    }
+
+    /// Listen for new entries.
+    /// Only here the consumer connects to the Redis server.
+    /// It handles reconnection and backoff for you.
+    ///
+    /// On every connection, the entries which are still pending for this consumer are read first,
+    /// as they were not acknowledged before the consumer stopped or the connection was lost.
+    ///
+    /// # Errors
+    /// Returns [Error::NoStreams] if no stream is added with `.with_stream()`,
+    /// or an error if the first connection attempt fails.
+    ///
+    /// [Error::NoStreams]: crate::Error::NoStreams
+    pub async fn listen(&self) -> crate::Result<impl Stream<Item = Message> + '_> {
+        if self.streams.is_empty() {
+            return Err(crate::Error::NoStreams);
+        }
+        self.connect(true).await?;
+
+        let stream = stream! {
+            // Where claiming continues in every stream.
+            let mut cursors = HashMap::new();
+            let mut next_claim = Instant::now();
+
+            // Reconnect until an error which will not be resolved by retrying.
+            let fatal = 'outer: loop {
+                let mut conn = match self.connect(false).await {
+                    Ok(conn) => conn,
+                    Err(e) if !self.retry_policy.retries(&e) => {
+                        break 'outer e;
+                    }
+                    Err(e) => {
+                        warn!("failed to connect to server: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let client_id = match self.initialize(&mut conn).await {
+                    Ok(client_id) => client_id,
+                    Err(e) => {
+                        warn!("failed to initialize connection: {:?}", e);
+                        yield Message::Error(e.clone());
+                        if !self.retry_policy.retries(&e) {
+                            break 'outer e;
+                        }
+
+                        // Wait before the next attempt, as the connection itself succeeded.
+                        sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+                let mut sequence = 0;
+                // Read the entries which are pending for this consumer before the new ones.
+                let mut history = self
+                    .streams
+                    .iter()
+                    .map(|stream| (stream.clone(), StreamId::default()))
+                    .collect::<HashMap<_, _>>();
+
+                yield Message::Connected { client_id };
+
+                loop {
+                    // Claim the abandoned entries before reading new ones,
+                    // once the own pending entries are read so claimed entries are not read twice.
+                    let res = match self.claim_idle {
+                        Some(min_idle) if history.is_empty() && Instant::now() >= next_claim => {
+                            next_claim = Instant::now() + min_idle;
+                            self.claim(&mut conn, min_idle, &mut cursors).await
+                        }
+                        _ => self.read(&mut conn, &mut history).await,
+                    };
+
+                    let e = match res {
+                        Ok(entries) => {
+                            let received = std::time::Instant::now();
+                            let timestamp = SystemTime::now();
+
+                            for (stream, entry) in entries {
+                                let metadata = Metadata {
+                                    received,
+                                    timestamp,
+                                    generation,
+                                    sequence,
+                                    stream_id: Some(entry.id),
+                                };
+                                sequence += 1;
+                                yield Message::Entry { stream, entry, metadata };
+                            }
+                            continue;
+                        }
+                        Err(e) => e,
+                    };
+
+                    // Reconnect after any error, which also recreates deleted consumer groups.
+                    let server_error = matches!(e, crate::Error::ServerError(_));
+                    if server_error {
+                        warn!("server replied with an error: {:?}", e);
+                        yield Message::Error(e.clone());
+                    } else {
+                        yield Message::Disconnected(e.clone());
+                    }
+                    if !self.retry_policy.retries(&e) {
+                        break 'outer e;
+                    }
+                    if server_error {
+                        sleep(Duration::from_secs(1)).await;
+                    }
+                    continue 'outer;
+                }
+            };
+
+            warn!("giving up after fatal error: {:?}", fatal);
+            yield Message::Fatal(fatal);
+        };
+
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    async fn next<S: Stream<Item = Message> + Unpin>(stream: &mut S) -> Message {
+        tokio::time::timeout(Duration::from_secs(2), stream.next())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message")
+    }
+
+    #[tokio::test]
+    async fn test_stream_sub() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+        let key = format!("jobs-{}", std::process::id());
+
+        // Another consumer reads an entry and never acknowledges it.
+        redis::cmd("XGROUP")
+            .arg("CREATE")
+            .arg(&key)
+            .arg("workers")
+            .arg("$")
+            .arg("MKSTREAM")
+            .query_async::<_, ()>(&mut connection)
+            .await
+            .expect("failed to create consumer group");
+        let abandoned: String = redis::cmd("XADD")
+            .arg(&key)
+            .arg("*")
+            .arg("job")
+            .arg("abandoned")
+            .query_async(&mut connection)
+            .await
+            .expect("failed to add to stream");
+        redis::cmd("XREADGROUP")
+            .arg("GROUP")
+            .arg("workers")
+            .arg("crashed")
+            .arg("STREAMS")
+            .arg(&key)
+            .arg(">")
+            .query_async::<_, redis::Value>(&mut connection)
+            .await
+            .expect("failed to read from stream");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let stream_sub = StreamSub::new("127.0.0.1:6379", "workers", "worker")
+            .with_stream(&key)
+            .with_block(Duration::from_millis(100))
+            .with_autoclaim(Duration::from_millis(50));
+        let mut stream = stream_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+        assert!(next(&mut stream).await.is_connected());
+
+        let new: String = redis::cmd("XADD")
+            .arg(&key)
+            .arg("*")
+            .arg("job")
+            .arg("new")
+            .query_async(&mut connection)
+            .await
+            .expect("failed to add to stream");
+
+        let mut ids = Vec::new();
+        for expected in [&abandoned, &new] {
+            match next(&mut stream).await {
+                Message::Entry {
+                    stream,
+                    entry,
+                    metadata,
+                } => {
+                    assert_eq!(stream, key);
+                    assert_eq!(entry.id.to_string(), *expected);
+                    assert_eq!(metadata.stream_id, Some(entry.id));
+                    ids.push(entry.id);
+                }
+                msg => panic!("message was not `Entry`: {:?}", msg),
+            }
+        }
+
+        assert_eq!(stream_sub.ack(&key, &ids).await.expect("failed to ack"), 2);
+        assert_eq!(stream_sub.ack(&key, &ids).await.expect("failed to ack"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_sub_rereads_pending() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+        let key = format!("restarts-{}", std::process::id());
+
+        assert!(matches!(
+            StreamSub::new("127.0.0.1:6379", "workers", "worker")
+                .listen()
+                .await,
+            Err(crate::Error::NoStreams)
+        ));
+
+        let stream_sub = StreamSub::new("127.0.0.1:6379", "workers", "worker")
+            .with_stream(&key)
+            .with_block(Duration::from_millis(100));
+        let mut stream = stream_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+        assert!(next(&mut stream).await.is_connected());
+
+        let unacked: String = redis::cmd("XADD")
+            .arg(&key)
+            .arg("*")
+            .arg("job")
+            .arg("unacked")
+            .query_async(&mut connection)
+            .await
+            .expect("failed to add to stream");
+        assert!(matches!(next(&mut stream).await, Message::Entry { .. }));
+
+        // The entry is read again after restarting, as it was never acknowledged.
+        drop(stream);
+        let mut stream = stream_sub
+            .listen()
+            .await
+            .expect("failed to connect to redis");
+        assert!(next(&mut stream).await.is_connected());
+        match next(&mut stream).await {
+            Message::Entry { entry, .. } => assert_eq!(entry.id.to_string(), unacked),
+            msg => panic!("message was not `Entry`: {:?}", msg),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::sync::mpsc;
+use tokio_stream::Stream;
+
+use crate::{Message, RedisSub};
+
+/// A channel or a pattern of channels which can be subscribed to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Target {
+    Channel(String),
+    Pattern(String),
+}
+
+/// Sender to a single subscription handle.
+#[derive(Debug)]
+struct Route {
+    /// Identifier of the handle.
+    id: u64,
+    /// Sender of the messages.
+    tx: mpsc::Sender<Message>,
+    /// Amount of messages dropped since the handle last received one, as it was full.
+    lagged: Arc<AtomicU64>,
+}
+
+/// Senders to the subscription handles, keyed by what they are subscribed to.
+#[derive(Debug)]
+pub(crate) struct Routes {
+    /// Amount of messages a handle can fall behind before missing messages.
+    capacity: usize,
+    /// Identifier given to the next subscription handle.
+    next_id: AtomicU64,
+    /// Senders of every subscription handle.
+    senders: Mutex<HashMap<Target, Vec<Route>>>,
+}
+
+impl Routes {
+    /// Create the routes, with the capacity of every handle.
+    ///
+    /// # Panics
+    /// Panics if the capacity is zero.
+    pub(crate) fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "subscription capacity must be positive");
+
+        Self {
+            capacity,
+            next_id: AtomicU64::new(0),
+            senders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Add a route for a new subscription handle.
+    fn add(&self, target: Target) -> (u64, mpsc::Receiver<Message>, Arc<AtomicU64>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(self.capacity);
+        let lagged = Arc::new(AtomicU64::new(0));
+        self.senders
+            .lock()
+            .unwrap()
+            .entry(target)
+            .or_default()
+            .push(Route {
+                id,
+                tx,
+                lagged: lagged.clone(),
+            });
+
+        (id, rx, lagged)
+    }
+
+    /// Remove every route, which ends the subscription handles once they received their messages.
+    pub(crate) fn close(&self) {
+        self.senders.lock().unwrap().clear();
+    }
+
+    /// Remove the route of a dropped subscription handle.
+    fn remove(&self, target: &Target, id: u64) {
+        let mut senders = self.senders.lock().unwrap();
+
+        if let Some(routes) = senders.get_mut(target) {
+            routes.retain(|route| route.id != id);
+            if routes.is_empty() {
+                senders.remove(target);
+            }
+        }
+    }
+
+    /// Send a message to the subscription handles of its channel or pattern.
+    pub(crate) fn dispatch(&self, msg: &Message) {
+        let senders = self.senders.lock().unwrap();
+        if senders.is_empty() {
+            return;
+        }
+
+        let send = |target: Target| {
+            for route in senders.get(&target).into_iter().flatten() {
+                // Drop the message for a handle which fell behind, it is told once it catches up.
+                if let Err(mpsc::error::TrySendError::Full(_)) = route.tx.try_send(msg.clone()) {
+                    route.lagged.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        };
+
+        match msg {
+            Message::Message { channel, .. } => send(Target::Channel(channel.clone())),
+            Message::PatternMessage { pattern, .. } => send(Target::Pattern(pattern.clone())),
+            Message::Binary {
+                pattern: Some(pattern),
+                ..
+            } => send(Target::Pattern(pattern.clone())),
+            Message::Binary { channel, .. } => send(Target::Channel(channel.clone())),
+            // Every affected handle is told about the gap.
+            Message::Gap {
+                channels, patterns, ..
+            } => {
+                for channel in channels {
+                    send(Target::Channel(channel.clone()));
+                }
+                for pattern in patterns {
+                    send(Target::Pattern(pattern.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Closes the routes when dropped, with the stream returned by `.listen()`.
+#[derive(Debug)]
+pub(crate) struct CloseRoutes<'a>(pub(crate) &'a Routes);
+
+impl Drop for CloseRoutes<'_> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// Handle to a single channel or pattern subscription.
+///
+/// This is a stream of only the messages of its channel or pattern,
+/// and the [Message::Gap]s affecting it,
+/// which are received while the stream returned by `.listen()` is polled.
+/// A handle which falls behind by more than its capacity misses the newest messages,
+/// this is reported by a [Message::Lagged] with the amount of missed messages,
+/// once the messages received before them are.
+/// The handle ends once the stream returned by `.listen()` ends or is dropped.
+/// Dropping the handle releases the subscription, see [RedisSub::subscription].
+#[derive(Debug)]
+pub struct Subscription {
+    /// The subscription object this handle is registered with.
+    sub: Arc<RedisSub>,
+    /// The channel or pattern of this handle.
+    target: Target,
+    /// Identifier of the route to this handle.
+    id: u64,
+    /// Receiver for the routed messages.
+    rx: mpsc::Receiver<Message>,
+    /// Amount of messages dropped as the receiver was full.
+    lagged: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// Create a new handle and register its route.
+    pub(crate) fn new(sub: Arc<RedisSub>, target: Target) -> Self {
+        let (id, rx, lagged) = sub.routes.add(target.clone());
+
+        Self {
+            sub,
+            target,
+            id,
+            rx,
+            lagged,
+        }
+    }
+
+    /// Take the amount of dropped messages, as a [Message::Lagged].
+    fn take_lagged(&self) -> Option<Message> {
+        match self.lagged.swap(0, Ordering::Relaxed) {
+            0 => None,
+            n => Some(Message::Lagged(n)),
+        }
+    }
+
+    /// The channel or pattern this handle is subscribed to.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match &self.target {
+            Target::Channel(name) | Target::Pattern(name) => name,
+        }
+    }
+
+    /// Whether this handle is subscribed to a pattern of channels.
+    #[must_use]
+    pub fn is_pattern(&self) -> bool {
+        matches!(self.target, Target::Pattern(_))
+    }
+
+    /// Receive the next message of this channel or pattern.
+    pub async fn recv(&mut self) -> Option<Message> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        // The buffered messages were received before the dropped ones, so they come first.
+        match self.rx.poll_recv(cx) {
+            Poll::Ready(Some(msg)) => Poll::Ready(Some(msg)),
+            poll => match self.take_lagged() {
+                Some(lagged) => Poll::Ready(Some(lagged)),
+                None => poll,
+            },
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.sub.routes.remove(&self.target, self.id);
+
+        // Unsubscribing is async, so it is done on the runtime.
+        let sub = self.sub.clone();
+        let target = self.target.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move { sub.release(target).await });
+            }
+            Err(_) => warn!("no runtime to release dropped subscription {:?}", target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+    use tokio_stream::StreamExt;
+
+    async fn next<S: Stream<Item = Message> + Unpin>(stream: &mut S) -> Message {
+        tokio::time::timeout(Duration::from_secs(2), stream.next())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a Message")
+    }
+
+    #[tokio::test]
+    async fn test_subscription_handles() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let (handle, mut receiver) = RedisSub::new("127.0.0.1:6379")
+            .spawn()
+            .await
+            .expect("failed to connect to redis");
+        assert!(next(&mut receiver).await.is_connected());
+
+        let mut first = handle
+            .subscription("handles".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        let mut second = handle
+            .subscription("handles".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        let mut other = handle
+            .subscription("other".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        assert!(next(&mut receiver).await.is_subscription());
+        assert!(next(&mut receiver).await.is_subscription());
+
+        connection
+            .publish::<&str, &str, u32>("handles", "1")
+            .await
+            .expect("failed to send publish command to Redis");
+        assert!(next(&mut first).await.is_message());
+        assert!(next(&mut second).await.is_message());
+
+        // The channel stays subscribed while a handle is left.
+        drop(first);
+        connection
+            .publish::<&str, &str, u32>("handles", "2")
+            .await
+            .expect("failed to send publish command to Redis");
+        match next(&mut second).await {
+            Message::Message { message, .. } => assert_eq!(message, "2"),
+            msg => panic!("message was not `Message`: {:?}", msg),
+        }
+
+        drop(second);
+        let mut msg = next(&mut receiver).await;
+        while msg.is_message() {
+            msg = next(&mut receiver).await;
+        }
+        assert!(
+            msg.is_unsubscription(),
+            "message after dropping all handles was not `Unsubscription`: {:?}",
+            msg
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), other.next())
+                .await
+                .is_err(),
+            "other channel received a message"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscription_lag_and_close() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let (handle, mut receiver) = RedisSub::new("127.0.0.1:6379")
+            .with_subscription_capacity(1)
+            .spawn()
+            .await
+            .expect("failed to connect to redis");
+        assert!(next(&mut receiver).await.is_connected());
+
+        let mut lagging = handle
+            .subscription("lagging".to_string())
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        assert!(next(&mut receiver).await.is_subscription());
+
+        for message in ["1", "2", "3"] {
+            connection
+                .publish::<&str, &str, u32>("lagging", message)
+                .await
+                .expect("failed to send publish command to Redis");
+            assert!(next(&mut receiver).await.is_message());
+        }
+
+        // The messages which did not fit are reported after the buffered message.
+        match lagging.recv().await {
+            Some(Message::Message { message, .. }) => assert_eq!(message, "1"),
+            msg => panic!("message was not `Message`: {:?}", msg),
+        }
+        assert!(matches!(lagging.recv().await, Some(Message::Lagged(2))));
+
+        // The handle ends with the stream.
+        handle
+            .shutdown(Duration::from_secs(1))
+            .await
+            .expect("failed to shut down");
+        let end = tokio::time::timeout(Duration::from_secs(2), lagging.recv())
+            .await
+            .expect("subscription did not end after the stream");
+        assert!(end.is_none(), "message after the stream ended: {:?}", end);
+    }
+}
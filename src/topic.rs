@@ -0,0 +1,307 @@
+//! Channel names built from templates with parameters, like `orders.{region}.{id}`.
+//!
+//! A parameter matches any non-empty text up to the text following it in the template,
+//! so values cannot be empty or contain that text, which [render] returns an error for.
+//! Use `#[derive(Topic)]` from the `derive` feature to implement [Topic] for a struct.
+
+use std::fmt::Display;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tokio_stream::Stream;
+
+use crate::codec::{Codec, CodecError};
+use crate::{glob, RedisSub, TypedChannel, TypedError, TypedSubscription};
+
+/// A message type published on channels named by a template.
+///
+/// Implemented with `#[derive(Topic)]`, where the parameters of the template are fields of the struct:
+///
+/// ```ignore
+/// #[derive(Topic, Serialize, Deserialize)]
+/// #[topic(channel = "orders.{region}.{id}", codec = Json)]
+/// struct OrderCreated {
+///     region: String,
+///     id: u64,
+///     amount: u32,
+/// }
+/// ```
+///
+/// This also declares the parameters as `OrderCreatedParams { region: String, id: u64 }`.
+pub trait Topic: Sized + 'static {
+    /// Parameters of the channel name.
+    type Params;
+    /// Codec of the payloads.
+    type Codec: Codec<Self> + Default + 'static;
+    /// Template of the channel name.
+    const TEMPLATE: &'static str;
+
+    /// The parameters of the channel this value is published on.
+    fn params(&self) -> Self::Params;
+
+    /// The channel name with the parameters.
+    ///
+    /// # Errors
+    /// Returns an error if a parameter is empty or contains the text following it in the template,
+    /// see [render].
+    fn channel(params: &Self::Params) -> crate::Result<String>;
+
+    /// Parse the parameters out of a channel name, returns `None` if it does not match the template.
+    fn parse_channel(channel: &str) -> Option<Self::Params>;
+
+    /// A pattern matching the channels with any parameters.
+    #[must_use]
+    fn pattern() -> String {
+        pattern(Self::TEMPLATE)
+    }
+
+    /// The typed channel with the parameters.
+    ///
+    /// # Errors
+    /// Returns an error if a parameter is invalid, see [Topic::channel].
+    fn typed_channel(params: &Self::Params) -> crate::Result<TypedChannel<Self>> {
+        Ok(TypedChannel::new(
+            &Self::channel(params)?,
+            Self::Codec::default(),
+        ))
+    }
+
+    /// The channel this value is published on, and its encoded payload.
+    ///
+    /// # Errors
+    /// Returns an error if a parameter is invalid, see [Topic::channel],
+    /// or if the codec cannot encode the value.
+    fn encode(&self) -> Result<(String, Vec<u8>), CodecError> {
+        let channel = Self::channel(&self.params())?;
+        let payload = Self::Codec::default().encode(self)?;
+
+        Ok((channel, payload))
+    }
+}
+
+/// Part of a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part<'a> {
+    Literal(&'a str),
+    Param(&'a str),
+}
+
+/// Split a template in its literal text and parameters.
+fn parts(template: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        if start > 0 {
+            parts.push(Part::Literal(&rest[..start]));
+        }
+        parts.push(Part::Param(&rest[start + 1..end]));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+
+    parts
+}
+
+/// Fill in the parameters of a template, in the order they appear in.
+///
+/// # Errors
+/// Returns an error if a value is empty or contains the text following its parameter,
+/// as the channel name could not be parsed back.
+///
+/// # Panics
+/// Panics if there are fewer values than parameters.
+pub fn render(template: &str, values: &[&dyn Display]) -> crate::Result<String> {
+    let parts = parts(template);
+    let mut values = values.iter();
+    let mut channel = String::new();
+
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Literal(literal) => channel.push_str(literal),
+            Part::Param(name) => {
+                let value = values
+                    .next()
+                    .unwrap_or_else(|| panic!("no value for parameter {}", name))
+                    .to_string();
+                let follows = match parts.get(i + 1) {
+                    Some(Part::Literal(literal)) => value.contains(literal),
+                    _ => false,
+                };
+                if value.is_empty() || follows {
+                    return Err(crate::Error::InvalidParameter {
+                        name: name.to_string(),
+                        value,
+                    });
+                }
+                channel.push_str(&value);
+            }
+        }
+    }
+
+    Ok(channel)
+}
+
+/// The values of the parameters in a channel name, in the order they appear in the template.
+///
+/// Returns `None` if the channel does not match the template.
+#[must_use]
+pub fn parse<'a>(template: &str, channel: &'a str) -> Option<Vec<&'a str>> {
+    let parts = parts(template);
+    let mut values = Vec::new();
+    let mut rest = channel;
+
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            Part::Literal(literal) => rest = rest.strip_prefix(literal)?,
+            Part::Param(_) => {
+                // A parameter ends where the next literal text starts.
+                let end = match parts.get(i + 1) {
+                    Some(Part::Literal(literal)) => rest.find(literal)?,
+                    _ => rest.len(),
+                };
+                if end == 0 {
+                    return None;
+                }
+                values.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    rest.is_empty().then_some(values)
+}
+
+/// A pattern matching the channel names of a template with any parameters.
+#[must_use]
+pub fn pattern(template: &str) -> String {
+    parts(template)
+        .into_iter()
+        .map(|part| match part {
+            Part::Literal(literal) => glob::escape(literal),
+            Part::Param(_) => "*".to_string(),
+        })
+        .collect()
+}
+
+impl RedisSub {
+    /// Subscribe to the channel of a topic with the parameters,
+    /// returning a handle which yields its decoded values.
+    ///
+    /// # Errors
+    /// Returns an error if a parameter is invalid, see [Topic::channel],
+    /// or if an error happens on the underlying TCP stream.
+    pub async fn topic_subscription<T: Topic>(
+        self: &Arc<Self>,
+        params: &T::Params,
+    ) -> crate::Result<TypedSubscription<T>> {
+        self.typed_subscription(&T::typed_channel(params)?).await
+    }
+
+    /// Subscribe to the channels of a topic with any parameters,
+    /// returning a handle which yields the parameters and the decoded values.
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn topic_psubscription<T: Topic>(
+        self: &Arc<Self>,
+    ) -> crate::Result<TopicSubscription<T>> {
+        let pattern = T::pattern();
+        let channel = TypedChannel::new(&pattern, T::Codec::default());
+        let subscription = self.psubscription(pattern).await?;
+
+        Ok(TopicSubscription {
+            subscription: TypedSubscription::new(subscription, channel),
+        })
+    }
+}
+
+/// Handle to the subscription of a topic with any parameters.
+///
+/// This is a stream of the parameters of the channels, and their decoded values.
+/// Messages on channels which match the pattern, but not the template, are skipped.
+/// Missed messages are reported by a [TypedError::Missed].
+#[derive(Debug)]
+pub struct TopicSubscription<T: Topic> {
+    /// The subscription of the pattern.
+    subscription: TypedSubscription<T>,
+}
+
+impl<T: Topic> TopicSubscription<T> {
+    /// Receive the next value of the topic.
+    pub async fn recv(&mut self) -> Option<Result<(T::Params, T), TypedError>> {
+        loop {
+            let next = self.subscription.recv_with_channel().await?;
+            if let Some(next) = with_params::<T>(next) {
+                return Some(next);
+            }
+        }
+    }
+}
+
+/// Replace the channel of a received value by its parameters,
+/// returns `None` if the channel does not match the template.
+fn with_params<T: Topic>(
+    next: Result<(String, T), TypedError>,
+) -> Option<Result<(T::Params, T), TypedError>> {
+    match next {
+        Ok((channel, value)) => Some(Ok((T::parse_channel(&channel)?, value))),
+        Err(TypedError::Decode(e)) if T::parse_channel(&e.channel).is_none() => None,
+        Err(e) => Some(Err(e)),
+    }
+}
+
+impl<T: Topic> Stream for TopicSubscription<T> {
+    type Item = Result<(T::Params, T), TypedError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let next = match self.subscription.poll_with_channel(cx) {
+                Poll::Ready(Some(next)) => next,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if let Some(next) = with_params::<T>(next) {
+                return Poll::Ready(Some(next));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template() {
+        let template = "orders.{region}.{id}";
+        assert_eq!(render(template, &[&"eu", &42]).unwrap(), "orders.eu.42");
+        assert_eq!(parse(template, "orders.eu.42"), Some(vec!["eu", "42"]));
+        // The last parameter matches the rest of the channel.
+        assert_eq!(parse(template, "orders.eu.42.1"), Some(vec!["eu", "42.1"]));
+        assert_eq!(parse(template, "orders..42"), None);
+        assert_eq!(parse(template, "invoices.eu.42"), None);
+        assert_eq!(pattern(template), "orders.*.*");
+
+        assert_eq!(parse("{a}-{b}", "x-y-z"), Some(vec!["x", "y-z"]));
+        assert_eq!(parse("[{a}]", "[x]"), Some(vec!["x"]));
+        assert_eq!(pattern("[{a}]"), "\\[*\\]");
+        // The last parameter can contain any text.
+        assert_eq!(render("{a}-{b}", &[&"x", &"y-z"]).unwrap(), "x-y-z");
+
+        // Values which could not be parsed back are rejected.
+        assert!(matches!(
+            render(template, &[&"e.u", &42]),
+            Err(crate::Error::InvalidParameter { name, .. }) if name == "region"
+        ));
+        assert!(render(template, &[&"", &42]).is_err());
+    }
+}
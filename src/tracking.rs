@@ -0,0 +1,74 @@
+use crate::resp::Response;
+use crate::ParserError;
+
+/// Channel the server publishes invalidations to, for clients redirecting their tracking to it.
+pub const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// Keys invalidated by client-side caching, see [RedisSub::with_tracking_invalidation].
+///
+/// [RedisSub::with_tracking_invalidation]: crate::RedisSub::with_tracking_invalidation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalidation {
+    /// These keys were modified, and cached values of them are stale.
+    Keys(Vec<String>),
+    /// Every cached value is stale, after `FLUSHALL` or `FLUSHDB`,
+    /// or after reconnecting, as invalidations may have been missed.
+    Flush,
+}
+
+impl Invalidation {
+    /// Whether a cached value of the key is stale.
+    #[must_use]
+    pub fn contains(&self, key: &str) -> bool {
+        match self {
+            Self::Keys(keys) => keys.iter().any(|k| k == key),
+            Self::Flush => true,
+        }
+    }
+
+    /// Parse the payload of an invalidation message, an array of keys or null.
+    ///
+    /// # Errors
+    /// Returns an error if the payload has unexpected types.
+    pub(crate) fn from_response(res: &Response) -> crate::Result<Self> {
+        let keys = match res {
+            Response::Array(keys) => keys,
+            Response::Null | Response::NullArray => return Ok(Self::Flush),
+            _ => return Err(ParserError::MalformedResponse.into()),
+        };
+
+        keys.iter()
+            .map(|key| match key {
+                Response::Bulk(key) => Ok(std::str::from_utf8(key)?.to_string()),
+                _ => Err(ParserError::MalformedResponse.into()),
+            })
+            .collect::<crate::Result<_>>()
+            .map(Self::Keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalidation() {
+        let res = Response::Array(vec![
+            Response::Bulk(b"user:1".to_vec()),
+            Response::Bulk(b"user:2".to_vec()),
+        ]);
+        let invalidation = Invalidation::from_response(&res).unwrap();
+        assert_eq!(
+            invalidation,
+            Invalidation::Keys(vec!["user:1".to_string(), "user:2".to_string()])
+        );
+        assert!(invalidation.contains("user:2"));
+        assert!(!invalidation.contains("user:3"));
+
+        let flush = Invalidation::from_response(&Response::Null).unwrap();
+        assert_eq!(flush, Invalidation::Flush);
+        assert!(flush.contains("user:3"));
+
+        assert!(Invalidation::from_response(&Response::Integer(1)).is_err());
+    }
+}
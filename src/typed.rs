@@ -0,0 +1,290 @@
+use std::fmt::{Debug, Formatter};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use thiserror::Error;
+use tokio_stream::Stream;
+
+use crate::codec::{Codec, CodecError};
+use crate::{Message, RedisSub, Subscription};
+
+/// A message which could not be decoded by the codec of its [TypedChannel].
+#[derive(Error, Debug)]
+#[error("Failed to decode the message on channel {channel}: {source}")]
+pub struct DecodeError {
+    /// The channel the message was received on.
+    pub channel: String,
+    /// The payload of the message.
+    pub payload: Vec<u8>,
+    /// The error returned by the codec.
+    pub source: CodecError,
+}
+
+/// An error receiving the next value of a [TypedSubscription].
+#[derive(Error, Debug)]
+pub enum TypedError {
+    /// A message could not be decoded.
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    /// Messages were missed, as reported by a [Message::Gap] or [Message::Lagged].
+    #[error("Messages were missed: {0:?}")]
+    Missed(Box<Message>),
+}
+
+/// A channel of which every message is a value encoded by a [Codec].
+///
+/// See the [codec] module for the available codecs.
+///
+/// [codec]: crate::codec
+pub struct TypedChannel<T> {
+    /// Name of the channel.
+    name: String,
+    /// Codec of the values.
+    codec: Arc<dyn Codec<T>>,
+}
+
+impl<T> TypedChannel<T> {
+    /// Declare a channel with the codec of its values.
+    #[must_use]
+    pub fn new<C>(name: &str, codec: C) -> Self
+    where
+        C: Codec<T> + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            codec: Arc::new(codec),
+        }
+    }
+
+    /// The name of the channel.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Encode a value, to publish it on the channel.
+    ///
+    /// # Errors
+    /// Returns an error if the codec cannot encode the value.
+    pub fn encode(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        self.codec.encode(value)
+    }
+
+    /// Decode the payload of a channel or pattern message.
+    ///
+    /// Returns `None` if the message has no payload.
+    pub fn decode(&self, msg: &Message) -> Option<Result<T, DecodeError>> {
+        decode(&*self.codec, msg)
+    }
+}
+
+/// Decode the payload of a channel or pattern message with a codec.
+///
+/// Returns `None` if the message has no payload.
+pub(crate) fn decode<T>(codec: &dyn Codec<T>, msg: &Message) -> Option<Result<T, DecodeError>> {
+    let channel = msg.channel()?;
+    let payload = msg.payload()?;
+
+    Some(codec.decode(payload).map_err(|source| DecodeError {
+        channel: channel.to_string(),
+        payload: payload.to_vec(),
+        source,
+    }))
+}
+
+impl<T> Clone for TypedChannel<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            codec: self.codec.clone(),
+        }
+    }
+}
+
+impl<T> Debug for TypedChannel<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedChannel")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl RedisSub {
+    /// Subscribe to a typed channel, returning a handle which yields its decoded values.
+    ///
+    /// Dropping the handle releases the subscription, like with [Subscription].
+    ///
+    /// # Errors
+    /// Returns an error if an error happens on the underlying TCP stream.
+    pub async fn typed_subscription<T>(
+        self: &Arc<Self>,
+        channel: &TypedChannel<T>,
+    ) -> crate::Result<TypedSubscription<T>> {
+        let subscription = self.subscription(channel.name.clone()).await?;
+
+        Ok(TypedSubscription::new(subscription, channel.clone()))
+    }
+}
+
+/// Handle to a typed channel subscription.
+///
+/// This is a stream of the decoded values of the channel.
+/// Messages which cannot be decoded are yielded as a [TypedError::Decode],
+/// after which the next messages are still received.
+/// Missed messages are reported by a [TypedError::Missed].
+#[derive(Debug)]
+pub struct TypedSubscription<T> {
+    /// The subscription of the channel.
+    subscription: Subscription,
+    /// The channel, with its codec.
+    channel: TypedChannel<T>,
+}
+
+impl<T> TypedSubscription<T> {
+    pub(crate) fn new(subscription: Subscription, channel: TypedChannel<T>) -> Self {
+        Self {
+            subscription,
+            channel,
+        }
+    }
+
+    /// Receive the next value of the channel.
+    pub async fn recv(&mut self) -> Option<Result<T, TypedError>> {
+        Some(self.recv_with_channel().await?.map(|(_, value)| value))
+    }
+
+    /// Receive the next value, with the channel it was received on.
+    pub(crate) async fn recv_with_channel(&mut self) -> Option<Result<(String, T), TypedError>> {
+        loop {
+            let msg = self.subscription.recv().await?;
+            if let Some(next) = self.decode(msg) {
+                return Some(next);
+            }
+        }
+    }
+
+    /// Poll for the next value, with the channel it was received on.
+    pub(crate) fn poll_with_channel(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(String, T), TypedError>>> {
+        loop {
+            let msg = match Pin::new(&mut self.subscription).poll_next(cx) {
+                Poll::Ready(Some(msg)) => msg,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if let Some(next) = self.decode(msg) {
+                return Poll::Ready(Some(next));
+            }
+        }
+    }
+
+    /// Decode a message, returns `None` if it has no payload and is not a missed notice.
+    fn decode(&self, msg: Message) -> Option<Result<(String, T), TypedError>> {
+        if let Message::Gap { .. } | Message::Lagged(_) = msg {
+            return Some(Err(TypedError::Missed(Box::new(msg))));
+        }
+
+        let value = match self.channel.decode(&msg)? {
+            Ok(value) => value,
+            Err(e) => return Some(Err(e.into())),
+        };
+        Some(Ok((msg.channel()?.to_string(), value)))
+    }
+}
+
+impl<T> Stream for TypedSubscription<T> {
+    type Item = Result<T, TypedError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_with_channel(cx)
+            .map(|next| next.map(|res| res.map(|(_, value)| value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Text;
+    use crate::subscription::Target;
+    use redis::AsyncCommands;
+    use std::time::Duration;
+
+    async fn next(subscription: &mut TypedSubscription<u32>) -> Result<u32, TypedError> {
+        tokio::time::timeout(Duration::from_secs(2), subscription.recv())
+            .await
+            .expect("timeout duration of 2 seconds was exceeded")
+            .expect("expected a value")
+    }
+
+    /// The decode error of a result.
+    fn decode_error(res: Result<u32, TypedError>) -> DecodeError {
+        match res {
+            Err(TypedError::Decode(e)) => e,
+            res => panic!("result was not a decode error: {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_missed_messages() {
+        let sub = Arc::new(RedisSub::new("127.0.0.1:6379").with_subscription_capacity(1));
+        let subscription = Subscription::new(sub.clone(), Target::Channel("typed".to_string()));
+        let mut subscription =
+            TypedSubscription::new(subscription, TypedChannel::<u32>::new("typed", Text));
+
+        // Only the first message fits in the handle.
+        for payload in ["1", "2", "3"] {
+            sub.routes.dispatch(&Message::test("typed", payload));
+        }
+
+        assert_eq!(next(&mut subscription).await.unwrap(), 1);
+        match next(&mut subscription).await {
+            Err(TypedError::Missed(msg)) => assert!(matches!(*msg, Message::Lagged(2))),
+            res => panic!("missed messages were not reported: {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_subscription() {
+        let client =
+            redis::Client::open("redis://127.0.0.1/").expect("failed to create Redis client");
+        let mut connection = client
+            .get_tokio_connection()
+            .await
+            .expect("failed to open Redis connection");
+
+        let channel = TypedChannel::<u32>::new("typed", Text);
+        let (handle, _receiver) = RedisSub::new("127.0.0.1:6379")
+            .spawn()
+            .await
+            .expect("failed to connect to redis");
+        let mut subscription = handle
+            .typed_subscription(&channel)
+            .await
+            .expect("failed to subscribe to new Redis channel");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for payload in [
+            channel.encode(&1).unwrap(),
+            b"x".to_vec(),
+            vec![0xff],
+            b"2".to_vec(),
+        ] {
+            connection
+                .publish::<&str, Vec<u8>, u32>("typed", payload)
+                .await
+                .expect("failed to send publish command to Redis");
+        }
+
+        assert_eq!(next(&mut subscription).await.unwrap(), 1);
+        let e = decode_error(next(&mut subscription).await);
+        assert_eq!(e.channel, "typed");
+        assert_eq!(e.payload, b"x");
+        // Binary payloads are decoded too.
+        assert_eq!(decode_error(next(&mut subscription).await).payload, [0xff]);
+        assert_eq!(next(&mut subscription).await.unwrap(), 2);
+    }
+}